@@ -0,0 +1,315 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use regex::{Captures, Regex};
+use sha2::{Digest, Sha256};
+
+use crate::fetch::{self, FetchOptions};
+
+/// A stored copy of a page
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot {
+    pub id: i64,
+    pub bmark_id: String,
+    /// Where the page was fetched from, after redirects
+    pub url: String,
+    /// Sha-256 of the stored file, which also names it
+    pub hash: String,
+    /// The stored file, relative to the archive directory. Html for pages, anything else, like
+    /// a pdf, is stored as it came.
+    pub path: String,
+    pub size: u64,
+    /// Number of images, stylesheets and scripts stored along with the page
+    pub assets: usize,
+    pub archived_at: String,
+}
+
+fn sha256(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Store `bytes` under `dir` named after their hash, keeping `extension` so browsers tell
+/// what the file holds. Storing the same content twice keeps the one copy.
+/// Returns the hash and the path relative to `dir`.
+fn store(dir: &Path, bytes: &[u8], extension: &str) -> Result<(String, String)> {
+    let hash = sha256(bytes);
+    let relative = format!("objects/{}/{}.{}", &hash[..2], hash, extension);
+    let path = dir.join(&relative);
+    if !path.exists() {
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(&path, bytes).with_context(|| format!("Couldn't write {}", path.display()))?;
+    }
+    Ok((hash, relative))
+}
+
+/// File extension for an asset, from its content type or else its URL
+fn extension(content_type: &str, url: &str) -> String {
+    let by_type = match content_type.split(';').next().unwrap_or_default().trim() {
+        "text/css" => Some("css"),
+        "text/javascript" | "application/javascript" | "application/x-javascript" => Some("js"),
+        "image/png" => Some("png"),
+        "image/jpeg" => Some("jpg"),
+        "image/gif" => Some("gif"),
+        "image/webp" => Some("webp"),
+        "image/svg+xml" => Some("svg"),
+        "image/x-icon" | "image/vnd.microsoft.icon" => Some("ico"),
+        "image/avif" => Some("avif"),
+        "application/pdf" => Some("pdf"),
+        "application/json" => Some("json"),
+        "application/xml" | "text/xml" => Some("xml"),
+        "text/plain" => Some("txt"),
+        _ => None,
+    };
+    if let Some(extension) = by_type {
+        return extension.to_owned();
+    }
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let last = path.rsplit('/').next().unwrap_or_default();
+    match last.rsplit_once('.') {
+        Some((_, extension)) if !extension.is_empty() && extension.len() <= 5 && extension.chars().all(|c| c.is_ascii_alphanumeric()) => {
+            extension.to_lowercase()
+        }
+        _ => String::from("bin"),
+    }
+}
+
+/// `html`, decoded from whatever charset it came in, declaring the UTF-8 it's stored as
+fn declare_utf8(html: &str) -> String {
+    let meta_re = Regex::new(r#"(?i)(?P<head><meta\b[^>]*?charset\s*=\s*["']?\s*)[a-z0-9_.:-]+"#).expect("Failed to create regex");
+    if meta_re.is_match(html) {
+        return meta_re.replace_all(html, "${head}utf-8").into_owned();
+    }
+    let head_re = Regex::new(r"(?i)<head\b[^>]*>").expect("Failed to create regex");
+    match head_re.find(html) {
+        Some(head) => format!("{}<meta charset=\"utf-8\">{}", &html[..head.end()], &html[head.end()..]),
+        None => format!("<meta charset=\"utf-8\">{}", html),
+    }
+}
+
+/// Fetch the page at `url` along with the images, stylesheets and scripts it links to, and
+/// store them in `dir`. The stored page refers to the stored assets, so it opens without a
+/// network. Assets which can't be fetched keep pointing at their original URL. What isn't
+/// html, like a pdf or an image, is stored as it came.
+pub fn snapshot(url: &str, dir: &Path, options: &FetchOptions) -> Result<Snapshot> {
+    let page = fetch::get(url, options)?;
+    if !page.is_html() {
+        let (hash, path) = store(dir, &page.body, &extension(&page.content_type, &page.url))?;
+        return Ok(Snapshot { url: page.url, hash, path, size: page.body.len() as u64, ..Default::default() });
+    }
+    let html = declare_utf8(&page.text());
+
+    // src of img and script, href of stylesheet and icon links
+    let asset_re = Regex::new(
+        r#"(?is)(?P<head><(?:img|script)\b[^>]*?\ssrc\s*=\s*|<link\b[^>]*?\shref\s*=\s*)(?P<quote>["'])(?P<url>[^"']+)["']"#,
+    )
+    .expect("Failed to create regex");
+    let rel_re = Regex::new(r#"(?i)\srel\s*=\s*["']?[^"'>]*\b(stylesheet|icon)\b"#).expect("Failed to create regex");
+    let link_tag_re = Regex::new(r"(?is)<link\b[^>]*>").expect("Failed to create regex");
+    // the rel of a link can come after its href, so find the links worth storing up front
+    let wanted_links = link_tag_re
+        .find_iter(&html)
+        .filter(|m| rel_re.is_match(m.as_str()))
+        .map(|m| m.start())
+        .collect::<Vec<_>>();
+
+    let mut assets = 0;
+    let mut stored: HashMap<String, Option<String>> = HashMap::new();
+    let rewritten = asset_re.replace_all(&html, |cap: &Captures| {
+        let original = cap[0].to_owned();
+        let start = cap.get(0).unwrap().start();
+        if cap["head"].to_lowercase().starts_with("<link") && !wanted_links.contains(&start) {
+            return original;
+        }
+        let asset_url = fetch::resolve_url(&page.url, &crate::import::unescape_html(&cap["url"]));
+        if asset_url.starts_with("data:") {
+            return original;
+        }
+        let relative = match stored.get(&asset_url) {
+            Some(relative) => relative.clone(),
+            None => {
+                let relative = fetch::get(&asset_url, options)
+                    .and_then(|asset| store(dir, &asset.body, &extension(&asset.content_type, &asset.url)))
+                    .map(|(_, relative)| relative)
+                    .ok();
+                if relative.is_some() {
+                    assets += 1;
+                }
+                stored.insert(asset_url, relative.clone());
+                relative
+            }
+        };
+        match relative {
+            // the page is stored two levels down too, in objects/<xx>/
+            Some(relative) => format!("{}{}../../{}{}", &cap["head"], &cap["quote"], relative, &cap["quote"]),
+            None => original,
+        }
+    });
+
+    let (hash, path) = store(dir, rewritten.as_bytes(), "html")?;
+    Ok(Snapshot {
+        url: page.url,
+        hash,
+        path,
+        size: rewritten.len() as u64,
+        assets,
+        ..Default::default()
+    })
+}
+
+/// Where the stored file of `snapshot` is
+pub fn file(dir: &Path, snapshot: &Snapshot) -> PathBuf {
+    dir.join(&snapshot.path)
+}
+
+/// Files `gc` deleted
+#[derive(Debug, Default)]
+pub struct GcReport {
+    pub files: usize,
+    pub bytes: u64,
+}
+
+/// Delete the files stored in `dir` which are neither one of `pages` (paths of snapshots, as
+/// in `Snapshot::path`) nor an asset one of them refers to. Pages share their assets, and
+/// the same page can be snapshotted for two bookmarks, so files only go once nothing uses them.
+pub fn gc<'a>(dir: &Path, pages: impl IntoIterator<Item = &'a str>) -> Result<GcReport> {
+    let asset_re = Regex::new(r"\.\./\.\./(objects/[0-9a-f]{2}/[0-9a-f]{64}\.[0-9a-z]+)").expect("Failed to create regex");
+    let mut kept = HashSet::new();
+    for page in pages {
+        match fs::read(dir.join(page)) {
+            Ok(html) => kept.extend(asset_re.captures_iter(&String::from_utf8_lossy(&html)).map(|cap| cap[1].to_owned())),
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e).with_context(|| format!("Couldn't read {}", page)),
+        }
+        kept.insert(page.to_owned());
+    }
+
+    let mut report = GcReport::default();
+    let objects = dir.join("objects");
+    if !objects.is_dir() {
+        return Ok(report);
+    }
+    for sub in fs::read_dir(&objects)? {
+        let sub = sub?.path();
+        if !sub.is_dir() {
+            continue;
+        }
+        for entry in fs::read_dir(&sub)? {
+            let path = entry?.path();
+            let relative = path.strip_prefix(dir)?.to_string_lossy().replace('\\', "/");
+            if !kept.contains(&relative) {
+                report.bytes += fs::metadata(&path)?.len();
+                fs::remove_file(&path).with_context(|| format!("Couldn't delete {}", path.display()))?;
+                report.files += 1;
+            }
+        }
+        // only goes when it's left empty
+        _ = fs::remove_dir(&sub);
+    }
+    Ok(report)
+}
+
+#[test]
+fn stores_page_and_assets_by_hash() -> Result<()> {
+    use crate::fetch::{response, serve};
+
+    let base = serve(|path| match path {
+        "/post" => response(
+            "200 OK",
+            &[("Content-Type", "text/html")],
+            "<html><head><link href=\"/style.css\" rel=\"stylesheet\"><link rel=\"canonical\" href=\"/post\"></head>\
+             <body><img src='img/a.png'><img src=\"/img/a.png\"><script src=\"/missing.js\"></script></body></html>",
+        ),
+        "/style.css" => response("200 OK", &[("Content-Type", "text/css")], "body { color: red }"),
+        "/img/a.png" => response("200 OK", &[("Content-Type", "image/png")], "png"),
+        _ => response("404 Not Found", &[], ""),
+    });
+    let dir = std::env::temp_dir().join(format!("bmark-archive-test-{}", std::process::id()));
+    _ = fs::remove_dir_all(&dir);
+
+    let snapshot = snapshot(&format!("{}/post", base), &dir, &FetchOptions::default())?;
+    assert_eq!(snapshot.assets, 2);
+    let html = fs::read_to_string(file(&dir, &snapshot))?;
+    assert_eq!(snapshot.hash, sha256(html.as_bytes()));
+    let css = format!("objects/{0}/{1}.css", &sha256(b"body { color: red }")[..2], sha256(b"body { color: red }"));
+    assert!(html.contains(&format!("<link href=\"../../{}\" rel=\"stylesheet\">", css)));
+    assert!(dir.join(&css).exists());
+    let png = format!("../../objects/{0}/{1}.png", &sha256(b"png")[..2], sha256(b"png"));
+    assert!(html.contains(&format!("<img src='{}'><img src=\"{}\">", png, png)));
+    assert!(html.contains("<link rel=\"canonical\" href=\"/post\">"));
+    assert!(html.contains("<script src=\"/missing.js\">"));
+
+    // the same content is stored once
+    let again = self::snapshot(&format!("{}/post", base), &dir, &FetchOptions::default())?;
+    assert_eq!(again.path, snapshot.path);
+
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn stores_other_files_as_they_came() -> Result<()> {
+    use crate::fetch::{response, serve};
+
+    let base = serve(|path| match path {
+        "/paper" => response("200 OK", &[("Content-Type", "application/pdf")], "%PDF-1.4 caf\u{e9}"),
+        // utf-8 bytes read as windows-1252, as the page says they are
+        "/old" => response(
+            "200 OK",
+            &[("Content-Type", "text/html; charset=windows-1252")],
+            "<html><head><meta charset=\"windows-1252\"></head><body>caf\u{e9}</body></html>",
+        ),
+        "/bare" => response("200 OK", &[("Content-Type", "text/html; charset=iso-8859-1")], "<html><head><title>caf\u{e9}</title></head></html>"),
+        _ => response("404 Not Found", &[], ""),
+    });
+    let dir = std::env::temp_dir().join(format!("bmark-archive-files-test-{}", std::process::id()));
+    _ = fs::remove_dir_all(&dir);
+
+    let paper = snapshot(&format!("{}/paper", base), &dir, &FetchOptions::default())?;
+    assert!(paper.path.ends_with(".pdf"));
+    assert_eq!(fs::read(file(&dir, &paper))?, "%PDF-1.4 caf\u{e9}".as_bytes());
+    assert_eq!(paper.hash, sha256("%PDF-1.4 caf\u{e9}".as_bytes()));
+
+    let old = snapshot(&format!("{}/old", base), &dir, &FetchOptions::default())?;
+    assert!(old.path.ends_with(".html"));
+    assert_eq!(
+        fs::read_to_string(file(&dir, &old))?,
+        "<html><head><meta charset=\"utf-8\"></head><body>caf\u{c3}\u{a9}</body></html>"
+    );
+    let bare = snapshot(&format!("{}/bare", base), &dir, &FetchOptions::default())?;
+    assert!(fs::read_to_string(file(&dir, &bare))?.starts_with("<html><head><meta charset=\"utf-8\"><title>"));
+
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn gc_keeps_what_pages_still_use() -> Result<()> {
+    use crate::fetch::{response, serve};
+
+    let base = serve(|path| match path {
+        "/kept" => response("200 OK", &[("Content-Type", "text/html")], "<img src=\"/shared.png\">"),
+        "/dropped" => response("200 OK", &[("Content-Type", "text/html")], "<img src=\"/shared.png\"><img src=\"/own.png\">"),
+        "/shared.png" => response("200 OK", &[("Content-Type", "image/png")], "shared"),
+        "/own.png" => response("200 OK", &[("Content-Type", "image/png")], "own"),
+        _ => response("404 Not Found", &[], ""),
+    });
+    let dir = std::env::temp_dir().join(format!("bmark-archive-gc-test-{}", std::process::id()));
+    _ = fs::remove_dir_all(&dir);
+
+    let kept = snapshot(&format!("{}/kept", base), &dir, &FetchOptions::default())?;
+    let dropped = snapshot(&format!("{}/dropped", base), &dir, &FetchOptions::default())?;
+    let report = gc(&dir, [kept.path.as_str()])?;
+    assert_eq!((report.files, report.bytes), (2, dropped.size + 3));
+    assert!(file(&dir, &kept).exists());
+    assert!(!file(&dir, &dropped).exists());
+    assert!(dir.join(format!("objects/{0}/{1}.png", &sha256(b"shared")[..2], sha256(b"shared"))).exists());
+    assert_eq!(gc(&dir, [kept.path.as_str()])?.files, 0);
+
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
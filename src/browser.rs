@@ -0,0 +1,82 @@
+use std::{env, process::Command};
+
+use anyhow::{bail, Context, Result};
+
+/// Command used to open URLs when neither `--with` nor `$BROWSER` tells otherwise
+#[cfg(target_os = "macos")]
+const DEFAULT_OPENER: &str = "open";
+#[cfg(not(target_os = "macos"))]
+const DEFAULT_OPENER: &str = "xdg-open";
+
+/// Schemes of the URLs which are opened. Anything else, like a `-` some import left at the
+/// start, could be taken for an option of the opener.
+const SCHEMES: &[&str] = &["http", "https", "file"];
+
+fn is_openable(url: &str) -> bool {
+    url.split_once(':').is_some_and(|(scheme, _)| SCHEMES.contains(&scheme.to_lowercase().as_str()))
+}
+
+/// Build the command line opening `url` with `opener`. `%s` in the opener is replaced with the
+/// URL, otherwise the URL is passed as the last argument.
+fn command_line(opener: &str, url: &str) -> Vec<String> {
+    let mut args = opener.split_whitespace().map(String::from).collect::<Vec<_>>();
+    if args.iter().any(|a| a.contains("%s")) {
+        for arg in args.iter_mut() {
+            *arg = arg.replace("%s", url);
+        }
+    } else {
+        args.push(url.to_owned());
+    }
+    args
+}
+
+/// Open `url` with `with` if given, else with the first entry of `$BROWSER` (which, by
+/// convention, may list several commands separated by `:`), else with the system opener.
+/// `$BROWSER` is where the command is configured for good.
+pub fn launch(url: &str, with: Option<&str>) -> Result<()> {
+    if !is_openable(url) {
+        bail!("Won't open {}, only http, https and file URLs are opened", url);
+    }
+    let browser = env::var("BROWSER").ok();
+    let opener = with
+        .or_else(|| browser.as_deref().and_then(|b| b.split(':').find(|c| !c.trim().is_empty())))
+        .unwrap_or(DEFAULT_OPENER);
+    // the URL must never end up being the program which is run
+    match opener.split_whitespace().next() {
+        None => bail!("The command to open URLs with is empty"),
+        Some(program) if program.contains("%s") => bail!("The command to open URLs with can't be the URL itself"),
+        Some(_) => {}
+    }
+
+    let args = command_line(opener, url);
+    let status = Command::new(&args[0])
+        .args(&args[1..])
+        .status()
+        .with_context(|| format!("Failed to run {}", args[0]))?;
+    if !status.success() {
+        bail!("{} exited with {}", args[0], status);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_command_line() {
+    assert_eq!(
+        command_line("firefox --new-tab", "https://a.com"),
+        ["firefox", "--new-tab", "https://a.com"]
+    );
+    assert_eq!(
+        command_line("chromium --app=%s", "https://a.com"),
+        ["chromium", "--app=https://a.com"]
+    );
+}
+
+#[test]
+fn refuses_what_isnt_a_url_or_a_command() {
+    assert!(is_openable("https://a.com") && is_openable("HTTP://a.com") && is_openable("file:///tmp/a.html"));
+    assert!(!is_openable("--help") && !is_openable("-e/bin/sh") && !is_openable("javascript:alert(1)"));
+    assert!(launch("--version", Some("true")).is_err());
+    assert!(launch("https://a.com", Some("  ")).is_err());
+    assert!(launch("https://a.com", Some("%s")).is_err());
+    assert!(launch("https://a.com", Some("true")).is_ok());
+}
@@ -0,0 +1,194 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{mpsc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::fetch::{self, FetchOptions};
+
+/// Redirects followed at most before giving up on a URL
+const MAX_REDIRECTS: usize = 10;
+
+#[derive(Clone, Copy)]
+pub struct CheckOptions {
+    /// URLs requested at the same time
+    pub parallel: usize,
+    /// Least time between two requests to the same host
+    pub per_host_delay: Duration,
+    pub fetch: FetchOptions,
+}
+
+impl Default for CheckOptions {
+    fn default() -> Self {
+        CheckOptions {
+            parallel: 8,
+            per_host_delay: Duration::from_secs(1),
+            fetch: FetchOptions::default(),
+        }
+    }
+}
+
+/// Outcome of requesting a bookmark's URL
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LinkStatus {
+    /// Left out of dumps, where the link's history is part of its bookmark
+    #[serde(skip)]
+    pub bmark_id: String,
+    pub url: String,
+    /// Status of the last response, none when no response came at all
+    pub status: Option<u16>,
+    /// Where the redirects (if any) ended up
+    pub final_url: String,
+    /// Whether every redirect on the way was a permanent one (301 or 308)
+    pub permanent: bool,
+    pub error: Option<String>,
+    pub checked_at: String,
+}
+
+impl LinkStatus {
+    pub fn is_broken(&self) -> bool {
+        self.error.is_some() || self.status.is_none_or(|s| s >= 400)
+    }
+
+    pub fn is_redirected(&self) -> bool {
+        self.final_url != self.url
+    }
+}
+
+/// Spaces out the requests to each host by `delay`
+struct HostSchedule {
+    delay: Duration,
+    next: Mutex<HashMap<String, Instant>>,
+}
+
+impl HostSchedule {
+    /// Wait for the host's turn
+    fn wait(&self, host: &str) {
+        let at = {
+            let mut next = self.next.lock().unwrap();
+            let now = Instant::now();
+            let at = next.get(host).copied().filter(|at| *at > now).unwrap_or(now);
+            next.insert(host.to_owned(), at + self.delay);
+            at
+        };
+        thread::sleep(at.saturating_duration_since(Instant::now()));
+    }
+}
+
+/// Request `url`, following redirects by hand to tell the permanent ones apart
+fn check_url(agent: &ureq::Agent, schedule: &HostSchedule, bmark_id: &str, url: &str) -> LinkStatus {
+    let mut status = LinkStatus {
+        bmark_id: bmark_id.to_owned(),
+        url: url.to_owned(),
+        final_url: url.to_owned(),
+        permanent: true,
+        ..Default::default()
+    };
+    for _ in 0..=MAX_REDIRECTS {
+        schedule.wait(fetch::host(&status.final_url));
+        let response = match agent.get(&status.final_url).call() {
+            Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+            Err(e) => {
+                status.status = None;
+                status.error = Some(e.to_string());
+                break;
+            }
+        };
+        status.status = Some(response.status());
+        let location = response.header("Location").filter(|_| (300..400).contains(&response.status()));
+        let Some(location) = location else { break };
+        status.permanent &= matches!(response.status(), 301 | 308);
+        status.final_url = fetch::resolve_url(&status.final_url, location);
+    }
+    if status.status.is_some_and(|s| (300..400).contains(&s)) && status.error.is_none() {
+        status.error = Some(String::from("too many redirects"));
+    }
+    status.permanent &= status.is_redirected();
+    status.checked_at = format!("{}", crate::date::get_current_datetime());
+    status
+}
+
+/// Check each `(bookmark id, url)`, `options.parallel` at a time, calling `progress` on the
+/// calling thread with each result as it comes. An error from `progress` stops the checks
+/// which haven't started yet and is returned. Results are returned in the order of `links`.
+pub fn check(
+    links: Vec<(String, String)>,
+    options: &CheckOptions,
+    mut progress: impl FnMut(&LinkStatus) -> Result<()>,
+) -> Result<Vec<LinkStatus>> {
+    let agent = fetch::agent_builder(&options.fetch).redirects(0).build();
+    let schedule = HostSchedule { delay: options.per_host_delay, next: Mutex::new(HashMap::new()) };
+    let total = links.len();
+    let queue = Mutex::new(links.into_iter().enumerate().collect::<VecDeque<_>>());
+    let mut results = Vec::with_capacity(total);
+
+    thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel();
+        for _ in 0..options.parallel.clamp(1, total.max(1)) {
+            let (sender, agent, schedule, queue) = (sender.clone(), &agent, &schedule, &queue);
+            scope.spawn(move || loop {
+                let Some((i, (bmark_id, url))) = queue.lock().unwrap().pop_front() else { break };
+                if sender.send((i, check_url(agent, schedule, &bmark_id, &url))).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+        for (i, status) in receiver {
+            if let Err(e) = progress(&status) {
+                queue.lock().unwrap().clear();
+                return Err(e);
+            }
+            results.push((i, status));
+        }
+        Ok(())
+    })?;
+
+    results.sort_by_key(|(i, _)| *i);
+    Ok(results.into_iter().map(|(_, status)| status).collect())
+}
+
+#[test]
+fn follows_redirects_and_spaces_out_requests() {
+    use crate::fetch::{response, serve};
+
+    let base = serve(|path| match path {
+        "/ok" => response("200 OK", &[], "fine"),
+        "/moved" => response("301 Moved Permanently", &[("Location", "/ok")], ""),
+        "/chain" => response("308 Permanent Redirect", &[("Location", "/temp")], ""),
+        "/temp" => response("302 Found", &[("Location", "/ok")], ""),
+        "/loop" => response("301 Moved Permanently", &[("Location", "/loop")], ""),
+        _ => response("404 Not Found", &[], ""),
+    });
+    let links = ["/ok", "/moved", "/chain", "/gone", "/loop"]
+        .iter()
+        .map(|path| (path.to_string(), format!("{}{}", base, path)))
+        .collect::<Vec<_>>();
+    let options = CheckOptions { parallel: 4, per_host_delay: Duration::from_millis(20), ..Default::default() };
+
+    let started = Instant::now();
+    let results = check(links, &options, |_| Ok(())).unwrap();
+    // 18 requests to the one host, at least 20ms apart
+    assert!(started.elapsed() >= Duration::from_millis(17 * 20));
+
+    let summary = results
+        .iter()
+        .map(|s| (s.bmark_id.as_str(), s.status, s.final_url.replace(&base, ""), s.permanent, s.is_broken()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        summary,
+        [
+            ("/ok", Some(200), String::from("/ok"), false, false),
+            ("/moved", Some(200), String::from("/ok"), true, false),
+            ("/chain", Some(200), String::from("/ok"), false, false),
+            ("/gone", Some(404), String::from("/gone"), false, true),
+            ("/loop", Some(301), String::from("/loop"), false, true),
+        ]
+    );
+    assert_eq!(results[4].error.as_deref(), Some("too many redirects"));
+}
@@ -1,9 +1,56 @@
 use std::path::PathBuf;
 
-use clap::{value_parser, Arg, ArgAction, ArgGroup, ArgMatches, Command};
+use bmark_rs::{date::parse_duration, rescue::WAYBACK_URL, ReadStatus};
+use clap::{builder::NonEmptyStringValueParser, value_parser, Arg, ArgAction, ArgGroup, ArgMatches, Command};
+
+/// Options picking bookmarks by tag and category, shared by the commands which take a selection
+fn filter_args() -> [Arg; 6] {
+    [
+        Arg::new("tag")
+            .short('t')
+            .long("tag")
+            .action(ArgAction::Append)
+            .help("Only bookmarks related to tag [support multiple tags]"),
+        Arg::new("tag-mode")
+            .long("tag-mode")
+            .default_value("any")
+            .value_parser(["all", "any"])
+            .help("When 'all' enabled it'll strictly look for the bookmarks which have all the tags given by user"),
+        Arg::new("category")
+            .long("category")
+            .help("Only bookmarks in this category or the ones nested under it"),
+        Arg::new("status")
+            .long("status")
+            .value_parser(|s: &str| s.parse::<ReadStatus>().map_err(|e| e.to_string()))
+            .help("Only bookmarks with this reading status: unread, reading, read or archived"),
+        Arg::new("pinned")
+            .long("pinned")
+            .action(ArgAction::SetTrue)
+            .help("Only pinned bookmarks"),
+        Arg::new("min-rating")
+            .long("min-rating")
+            .value_parser(value_parser!(u8).range(1..=5))
+            .help("Only bookmarks rated at least this many stars"),
+    ]
+}
+
+/// Options on fetching the page of a new bookmark, shared by the commands which add one
+fn fetch_args() -> [Arg; 2] {
+    [
+        Arg::new("no-fetch")
+            .long("no-fetch")
+            .action(ArgAction::SetTrue)
+            .help("Don't fetch the page for its title, description and other metadata"),
+        Arg::new("timeout")
+            .long("timeout")
+            .default_value("10")
+            .value_parser(value_parser!(u64))
+            .help("Seconds to wait for the page when fetching it"),
+    ]
+}
 
 pub fn build_args() -> ArgMatches {
-    return Command::new("bmark")
+    Command::new("bmark")
         // .no_binary_name(true)
         .author("abhay")
         .version("0.0.1") // will make dynamic
@@ -35,8 +82,7 @@ pub fn build_args() -> ArgMatches {
                 Arg::new("name")
                     .short('n')
                     .long("name")
-                    .required(true)
-                    .help("Name for the URL"),
+                    .help("Name for the URL [default: title of the page]"),
                 Arg::new("tags")
                     .short('t')
                     .long("tag")
@@ -53,7 +99,8 @@ pub fn build_args() -> ArgMatches {
                 //     .long("date")
                 //     .value_parser(validate_date)
                 //     .help("Date of when the bookmark was added [default: today, format: yyyy-mm-dd]"),
-            ]),
+            ])
+            .args(fetch_args()),
         )
         .subcommand(
             Command::new("list")
@@ -63,14 +110,26 @@ pub fn build_args() -> ArgMatches {
                         .long("all")
                         .action(ArgAction::SetTrue)
                         .help("List out all for bookmark. If --tag/-t not passed, --all will be considered"),
-                    // can't this be multiple yet "all" be single ?
-                    Arg::new("tag")
-                        .short('t')
-                        .long("tag")
-                        .action(ArgAction::Append)
-                        .help("List out bookmarks related to tag [support multiple tags]"),
                 ])
-                .group(ArgGroup::new("output").args(["all", "tag"]).required(true))
+                // can't this be multiple yet "all" be single ?
+                .args(filter_args())
+                .args([
+                    Arg::new("broken")
+                        .long("broken")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with("redirected")
+                        .help("Only bookmarks whose link was broken when last checked"),
+                    Arg::new("redirected")
+                        .long("redirected")
+                        .action(ArgAction::SetTrue)
+                        .help("Only bookmarks whose link redirected elsewhere when last checked"),
+                ])
+                .group(
+                    ArgGroup::new("output")
+                        .args(["all", "tag", "category", "status", "pinned", "min-rating", "broken", "redirected"])
+                        .multiple(true)
+                        .required(true),
+                )
                 .arg(
                     // NOTE: Later, this needs to be changed to multi-value option where user can desire
                     // for any combination of the column present from the option with only
@@ -91,10 +150,546 @@ If provided \"desc\" or \"tags\" as value, they will be listed along with their
                         ),
                 )
                 .arg(
-                    Arg::new("tag-mode")
-                        .default_value("any")
-                        .value_parser(["all", "any"])
-                        .help("When 'all' enabled it'll strictly look for the bookmarks which have all the tags given by user"))
+                    Arg::new("sort")
+                        .long("sort")
+                        .default_value("added")
+                        .value_parser(["added", "visits", "last-visited"])
+                        .help("Order of the listed bookmarks"),
+                )
+        )
+        .subcommand(
+            Command::new("open")
+                .about("Open bookmarks in the browser")
+                .after_help(
+                    "Bookmarks are opened with the command given with --with, else with the first command \
+                     in $BROWSER, else with xdg-open. Set $BROWSER in your shell profile to always open them \
+                     with another command, %s in it stands for the URL. Only http, https and file URLs are opened.",
+                )
+                .args([
+                    Arg::new("query")
+                        .required(true)
+                        .help("Id, unique id prefix or URL of a bookmark, or text to search the bookmarks for"),
+                    Arg::new("all")
+                        .short('a')
+                        .long("all")
+                        .action(ArgAction::SetTrue)
+                        .help("Open every matching bookmark instead of picking one"),
+                    Arg::new("with")
+                        .short('w')
+                        .long("with")
+                        .help("Command to open the URL with [default: $BROWSER, else xdg-open]"),
+                ]),
+        )
+        .subcommand(
+            Command::new("edit")
+                .about("Change the fields of a bookmark")
+                .args([
+                    Arg::new("id")
+                        .required(true)
+                        .help("Id, unique id prefix or URL of the bookmark to edit"),
+                    Arg::new("url")
+                        .short('u')
+                        .long("url")
+                        .help("New URL for the bookmark"),
+                    Arg::new("name")
+                        .short('n')
+                        .long("name")
+                        .help("New name for the bookmark [empty value clears it]"),
+                    Arg::new("description")
+                        .long("desc")
+                        .help("New note for the bookmark [empty value clears it]"),
+                    Arg::new("category")
+                        .short('c')
+                        .long("catg")
+                        .help("New category for the bookmark [empty value clears it]"),
+                    Arg::new("tags")
+                        .short('t')
+                        .long("tag")
+                        .action(ArgAction::Append)
+                        .help("Add tags to the bookmark [support multiple tags]"),
+                    Arg::new("untag")
+                        .long("untag")
+                        .action(ArgAction::Append)
+                        .help("Remove tags from the bookmark [support multiple tags]"),
+                ]),
+        )
+        .subcommand(
+            Command::new("show")
+                .about("Show a single bookmark")
+                .args([
+                    Arg::new("id")
+                        .required(true)
+                        .help("Id, unique id prefix or URL of the bookmark to show"),
+                    Arg::new("history")
+                        .long("history")
+                        .action(ArgAction::SetTrue)
+                        .help("Show how each field of the bookmark changed over time"),
+                ]),
+        )
+        .subcommand(
+            Command::new("note")
+                .about("Write markdown notes on a bookmark")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("add")
+                        .about("Add a note to a bookmark")
+                        .args([
+                            Arg::new("id")
+                                .required(true)
+                                .help("Id, unique id prefix or URL of the bookmark"),
+                            Arg::new("text")
+                                .required(true)
+                                .num_args(1..)
+                                .help("Text of the note, markdown is fine [- reads it from stdin]"),
+                        ]),
+                )
+                .subcommand(
+                    Command::new("list")
+                        .about("Show the notes of a bookmark")
+                        .arg(
+                            Arg::new("id")
+                                .required(true)
+                                .help("Id, unique id prefix or URL of the bookmark"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("edit")
+                        .about("Rewrite a note")
+                        .args([
+                            Arg::new("id")
+                                .required(true)
+                                .help("Id, unique id prefix or URL of the bookmark"),
+                            Arg::new("seq")
+                                .required(true)
+                                .value_parser(value_parser!(i64))
+                                .help("Number of the note, as note list shows it"),
+                            Arg::new("text")
+                                .num_args(1..)
+                                .help("New text of the note [default: edit it in $VISUAL or $EDITOR, - reads it from stdin]"),
+                        ]),
+                ),
+        )
+        .subcommand(
+            Command::new("remind")
+                .about("Get reminded about a bookmark, see the due command")
+                .args([
+                    Arg::new("id")
+                        .required(true)
+                        .help("Id, unique id prefix or URL of the bookmark"),
+                    Arg::new("when")
+                        .required(true)
+                        .num_args(2)
+                        .value_names(["in|on", "time"])
+                        .help("When to be reminded: 'in' and a span like 2w, or 'on' and a date like 2025-01-10"),
+                ]),
+        )
+        .subcommand(Command::new("due").about("List the bookmarks whose reminders have come due"))
+        .subcommand(
+            Command::new("snooze")
+                .about("Put off the reminder of a bookmark")
+                .args([
+                    Arg::new("id")
+                        .required(true)
+                        .help("Id, unique id prefix or URL of the bookmark"),
+                    Arg::new("when")
+                        .num_args(2)
+                        .value_names(["in|on", "time"])
+                        .default_values(["in", "1d"])
+                        .help("Until when: 'in' and a span like 3d, or 'on' and a date like 2025-01-10"),
+                ]),
+        )
+        .subcommand(
+            Command::new("dismiss")
+                .about("Drop the reminder of a bookmark")
+                .arg(
+                    Arg::new("id")
+                        .required(true)
+                        .help("Id, unique id prefix or URL of the bookmark"),
+                ),
+        )
+        .subcommand(
+            Command::new("pin")
+                .about("Pin a bookmark so it's listed first")
+                .args([
+                    Arg::new("id")
+                        .required(true)
+                        .help("Id, unique id prefix or URL of the bookmark"),
+                    Arg::new("unpin")
+                        .long("unpin")
+                        .action(ArgAction::SetTrue)
+                        .help("Unpin it instead"),
+                ]),
+        )
+        .subcommand(
+            Command::new("rate")
+                .about("Rate a bookmark from 1 to 5 stars")
+                .args([
+                    Arg::new("id")
+                        .required(true)
+                        .help("Id, unique id prefix or URL of the bookmark"),
+                    Arg::new("stars")
+                        .required_unless_present("clear")
+                        .value_parser(value_parser!(u8).range(1..=5))
+                        .help("Number of stars"),
+                    Arg::new("clear")
+                        .long("clear")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with("stars")
+                        .help("Remove the rating"),
+                ]),
+        )
+        .subcommand(
+            Command::new("later")
+                .about("Bookmark a page to read later, or queue a bookmarked one again")
+                .args([
+                    Arg::new("url").required(true).help("URL to read later"),
+                    Arg::new("tags")
+                        .short('t')
+                        .long("tag")
+                        .action(ArgAction::Append)
+                        .help("Provide tags to bookmark [support multiple tags]"),
+                ])
+                .args(fetch_args()),
+        )
+        .subcommand(
+            Command::new("next")
+                .about("Show the unread bookmark which has been waiting the longest")
+                .args([
+                    Arg::new("random")
+                        .short('r')
+                        .long("random")
+                        .action(ArgAction::SetTrue)
+                        .help("Pick any unread bookmark instead"),
+                    Arg::new("start")
+                        .long("start")
+                        .action(ArgAction::SetTrue)
+                        .help("Mark it as being read"),
+                ]),
+        )
+        .subcommand(
+            Command::new("done")
+                .about("Mark a bookmark as read")
+                .args([
+                    Arg::new("id")
+                        .required(true)
+                        .help("Id, unique id prefix or URL of the bookmark"),
+                    Arg::new("archive")
+                        .long("archive")
+                        .action(ArgAction::SetTrue)
+                        .help("Mark it as archived instead, read and put away"),
+                ]),
+        )
+        .subcommand(
+            Command::new("search")
+                .about("Find bookmarks by text")
+                .args([
+                    Arg::new("query")
+                        .required(true)
+                        .num_args(1..)
+                        .help("Text to look for in the URL, name, description, category or tags"),
+                    Arg::new("content")
+                        .long("content")
+                        .action(ArgAction::SetTrue)
+                        .help("Look in the text of the fetched and archived pages instead, for every word of the query"),
+                ]),
+        )
+        .subcommand(
+            Command::new("revert")
+                .about("Bring a bookmark back to one of its revisions")
+                .args([
+                    Arg::new("id")
+                        .required(true)
+                        .help("Id, unique id prefix or URL of the bookmark to revert"),
+                    Arg::new("to")
+                        .long("to")
+                        .required(true)
+                        .value_parser(value_parser!(i64))
+                        .help("Revision to revert to, as listed by show --history"),
+                ]),
+        )
+        .subcommand(
+            Command::new("tag")
+                .about("Manage tags")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("merge")
+                        .about("Retag everything under <from> with <into> and drop <from>")
+                        .args([
+                            Arg::new("from").required(true).help("Tag to merge away"),
+                            Arg::new("into").required(true).help("Tag to merge into"),
+                        ]),
+                ),
+        )
+        .subcommand(
+            Command::new("rm")
+                .about("Move a bookmark to trash")
+                .arg(
+                    Arg::new("id")
+                        .required(true)
+                        .help("Id, unique id prefix or URL of the bookmark to remove"),
+                ),
+        )
+        .subcommand(
+            Command::new("trash")
+                .about("Manage the removed bookmarks")
+                .subcommand_required(true)
+                .subcommand(Command::new("list").about("List the bookmarks in trash"))
+                .subcommand(
+                    Command::new("restore")
+                        .about("Bring a bookmark back from trash")
+                        .arg(
+                            Arg::new("id")
+                                .required(true)
+                                .help("Id, unique id prefix or URL of the bookmark to restore"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("empty")
+                        .about("Permanently delete the bookmarks in trash")
+                        .arg(
+                            Arg::new("older-than")
+                                .long("older-than")
+                                .value_parser(parse_duration)
+                                .help("Only purge bookmarks trashed at least this long ago [format: 30d, 2w, 12h]"),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("import")
+                .about("Add the bookmarks from another browser or bookmark manager")
+                .args([
+                    Arg::new("from")
+                        .long("from")
+                        .visible_alias("format")
+                        .required_unless_present("scan")
+                        .conflicts_with("scan")
+                        .value_parser(["firefox", "chrome", "chromium", "buku", "pinboard", "pocket", "json", "jsonl", "csv"])
+                        .help("Where the bookmarks come from"),
+                    Arg::new("path")
+                        .required_unless_present("scan")
+                        .value_parser(value_parser!(PathBuf))
+                        .help("File (or profile directory) to import from"),
+                    Arg::new("scan")
+                        .long("scan")
+                        .num_args(1..)
+                        .value_name("FILES")
+                        .value_parser(value_parser!(PathBuf))
+                        .help("Pick the URLs out of text, markdown or html files, asking about each one"),
+                    Arg::new("yes")
+                        .short('y')
+                        .long("yes")
+                        .action(ArgAction::SetTrue)
+                        .requires("scan")
+                        .help("Take every scanned URL without asking"),
+                    Arg::new("tag")
+                        .short('t')
+                        .long("tag")
+                        .action(ArgAction::Append)
+                        .requires("scan")
+                        .help("Tag to give every scanned URL [support multiple tags]"),
+                    Arg::new("new-ids")
+                        .long("new-ids")
+                        .action(ArgAction::SetTrue)
+                        .help("Give the bookmarks of a json/jsonl dump new ids instead of keeping theirs"),
+                    Arg::new("map")
+                        .long("map")
+                        .action(ArgAction::Append)
+                        .help("Columns to read the fields of a csv from, like url=Link,name=Title,tags=Labels [default: detected from the header]"),
+                    Arg::new("tag-sep")
+                        .long("tag-sep")
+                        .default_value(",")
+                        .value_parser(NonEmptyStringValueParser::new())
+                        .help("What the tags in a csv cell are separated with"),
+                    Arg::new("dry-run")
+                        .long("dry-run")
+                        .num_args(0..=1)
+                        .default_missing_value("10")
+                        .value_parser(value_parser!(usize))
+                        .help("Only show the first N bookmarks which would be imported and what would be skipped"),
+                ]),
+        )
+        .subcommand(
+            Command::new("export")
+                .about("Write out the bookmarks")
+                .args([
+                    Arg::new("format")
+                        .long("format")
+                        .default_value("json")
+                        .value_parser(["json", "jsonl", "markdown"])
+                        .help("Format to export in, json and jsonl keep every field"),
+                    Arg::new("group-by")
+                        .long("group-by")
+                        .default_value("category")
+                        .value_parser(["category", "tag"])
+                        .help("What to group the markdown list by"),
+                    Arg::new("favicons")
+                        .long("favicons")
+                        .action(ArgAction::SetTrue)
+                        .help("Show each site's icon in the markdown list, fetching the ones not cached yet"),
+                    Arg::new("out")
+                        .short('o')
+                        .long("out")
+                        .value_parser(value_parser!(PathBuf))
+                        .conflicts_with("vault")
+                        .help("File to write to [default: stdout]"),
+                    Arg::new("vault")
+                        .long("vault")
+                        .value_parser(value_parser!(PathBuf))
+                        .help("Write a markdown note per bookmark into this directory, updating the notes of an earlier export"),
+                ]),
+        )
+        .subcommand(
+            Command::new("archive")
+                .about("Keep a copy of the pages, with their images, stylesheets and scripts")
+                .args_conflicts_with_subcommands(true)
+                .args([
+                    Arg::new("query")
+                        .required(true)
+                        .help("Id, unique id prefix or URL of a bookmark, or text to search the bookmarks for, every match gets archived"),
+                    Arg::new("timeout")
+                        .long("timeout")
+                        .default_value("30")
+                        .value_parser(value_parser!(u64))
+                        .help("Seconds to wait for the page and each of its assets"),
+                ])
+                .subcommand(
+                    Command::new("open")
+                        .about("Open the latest copy of a bookmarked page")
+                        .args([
+                            Arg::new("id")
+                                .required(true)
+                                .help("Id, unique id prefix or URL of the bookmark"),
+                            Arg::new("with")
+                                .short('w')
+                                .long("with")
+                                .help("Command to open the copy with [default: $BROWSER, else xdg-open]"),
+                        ]),
+                )
+                .subcommand(
+                    Command::new("list")
+                        .about("List the copies kept of a bookmarked page")
+                        .arg(
+                            Arg::new("id")
+                                .required(true)
+                                .help("Id, unique id prefix or URL of the bookmark"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("gc")
+                        .about("Delete the stored files no copy uses anymore, like the ones of bookmarks purged from trash, which undo can't bring back after this"),
+                ),
+        )
+        .subcommand(
+            Command::new("rescue")
+                .about("Look up an archived copy of a dead link and keep it as an alternate link")
+                .args([
+                    Arg::new("id")
+                        .required(true)
+                        .help("Id, unique id prefix or URL of the bookmark to rescue"),
+                    Arg::new("archive-url")
+                        .long("archive-url")
+                        .default_value(WAYBACK_URL)
+                        .help("Wayback availability API to ask"),
+                    Arg::new("timeout")
+                        .long("timeout")
+                        .default_value("30")
+                        .value_parser(value_parser!(u64))
+                        .help("Seconds to wait for the archive"),
+                ]),
+        )
+        .subcommand(
+            Command::new("check")
+                .about("Request the bookmarked URLs and record which ones are broken or redirect")
+                .args(filter_args())
+                .args([
+                    Arg::new("parallel")
+                        .short('j')
+                        .long("parallel")
+                        .default_value("8")
+                        .value_parser(value_parser!(usize))
+                        .help("Number of URLs to request at the same time"),
+                    Arg::new("per-host-delay")
+                        .long("per-host-delay")
+                        .default_value("1000")
+                        .value_parser(value_parser!(u64))
+                        .help("Milliseconds to wait between two requests to the same host"),
+                    Arg::new("timeout")
+                        .long("timeout")
+                        .default_value("10")
+                        .value_parser(value_parser!(u64))
+                        .help("Seconds to wait for each response"),
+                    Arg::new("fix-redirects")
+                        .long("fix-redirects")
+                        .action(ArgAction::SetTrue)
+                        .help("Change the URLs which redirect permanently to where they lead"),
+                ]),
+        )
+        .subcommand(
+            Command::new("publish")
+                .about("Render the bookmarks as a static, searchable html site")
+                .arg(
+                    Arg::new("out")
+                        .short('o')
+                        .long("out")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                        .help("Directory to write the site to"),
+                )
+                .arg(
+                    Arg::new("title")
+                        .long("title")
+                        .default_value("Bookmarks")
+                        .help("Title of the site"),
+                )
+                .arg(
+                    Arg::new("favicons")
+                        .long("favicons")
+                        .action(ArgAction::SetTrue)
+                        .help("Show each site's icon, fetching the ones not cached yet"),
+                )
+                .args(filter_args()),
+        )
+        .subcommand(
+            Command::new("feed")
+                .about("Write an Atom or RSS feed of the bookmarks to stdout")
+                .arg(Arg::new("query").help("Only bookmarks whose URL, name, description, category or tags contain this"))
+                .args(filter_args())
+                .args([
+                    Arg::new("format")
+                        .long("format")
+                        .default_value("atom")
+                        .value_parser(["atom", "rss"])
+                        .help("Kind of feed"),
+                    Arg::new("title")
+                        .long("title")
+                        .help("Title of the feed [default: describes the tags, category and query]"),
+                    Arg::new("link")
+                        .long("link")
+                        .required_if_eq("format", "rss")
+                        .help("URL of the page the feed belongs to [required by RSS]"),
+                ]),
+        )
+        .subcommand(
+            Command::new("undo")
+                .about("Revert the last operations")
+                .arg(
+                    Arg::new("count")
+                        .default_value("1")
+                        .value_parser(value_parser!(usize))
+                        .help("Number of operations to revert"),
+                ),
+        )
+        .subcommand(
+            Command::new("history")
+                .about("Show the recorded operations")
+                .arg(
+                    Arg::new("limit")
+                        .short('l')
+                        .long("limit")
+                        .default_value("20")
+                        .value_parser(value_parser!(usize))
+                        .help("Number of operations to show"),
+                ),
         )
-        .get_matches();
+        .get_matches()
 }
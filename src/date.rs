@@ -43,7 +43,7 @@ where
         "December",
     ];
 
-    String::from(months[idx as usize])
+    String::from(months[idx as usize - 1])
 }
 
 impl Display for Datetime {
@@ -58,17 +58,13 @@ impl Display for Datetime {
 
 #[allow(dead_code)]
 fn is_leap_year(year: u32) -> bool {
-    return if year % 400 == 0 || (year % 4 == 0 && year % 100 != 0) {
-        true
-    } else {
-        false
-    };
+    year.is_multiple_of(400) || (year.is_multiple_of(4) && !year.is_multiple_of(100))
 }
 
 /// Get `DateTime` for provided `epoch` (seconds)
 /// This doesn't considers your timezone and returns `DateTime` which will be UTC in 24-hour format
 #[allow(dead_code)]
-fn get_datetime_for_epochs(epoch: u64) -> Datetime {
+pub fn get_datetime_for_epochs(epoch: u64) -> Datetime {
     let days_since_epoch = epoch / 86400;
     let mut cyear = 1970; // epoch year start
     let mut days_in_years = 0;
@@ -125,14 +121,23 @@ fn get_datetime_for_epochs(epoch: u64) -> Datetime {
     let cmin = (seconds_today % 3600) / 60;
     let csec = seconds_today - (chour * 3600 + cmin * 60);
 
-    return Datetime {
-        year: cyear as u32,
+    Datetime {
+        year: cyear,
         month: cmonth as u8,
         day: cday as u8,
         hour: chour as u8,
         minute: cmin as u8,
         second: csec as u8,
-    };
+    }
+}
+
+/// Seconds between 1601-01-01, where WebKit/Chromium timestamps start, and the unix epoch
+const WEBKIT_EPOCH_OFFSET: u64 = 11_644_473_600;
+
+/// Get `DateTime` for a WebKit timestamp, i.e. microseconds since 1601-01-01 UTC
+/// Timestamps from before the unix epoch are clamped to it
+pub fn get_datetime_for_webkit_time(micros: u64) -> Datetime {
+    get_datetime_for_epochs((micros / 1_000_000).saturating_sub(WEBKIT_EPOCH_OFFSET))
 }
 
 #[allow(dead_code)]
@@ -163,13 +168,19 @@ impl Error for DatetimeError {
     }
 }
 
-#[allow(dead_code)]
-fn parse_date(arg: &str) -> Result<Datetime> {
+/// Parse `yyyy-mm-dd hh:mm:ss`, the format sqlite's `current_timestamp` uses
+pub fn parse_date(arg: &str) -> Result<Datetime> {
     let re = Regex::new(r"^(?P<year>\d{4})-(?P<mon>\d{2})-(?P<day>\d{2}) (?P<hour>\d{2}):(?P<min>\d{2}):(?P<sec>\d{2})$")
         .map_err(|_| eprintln!("Failed to create regex")).unwrap();
-    let cap_dt = re.captures(arg).unwrap();
+    let Some(cap_dt) = re.captures(arg) else {
+        return Err(DatetimeError::ParsingError(format!(
+            "Invalid datetime '{}', expected yyyy-mm-dd hh:mm:ss",
+            arg
+        ))
+        .into());
+    };
 
-    let month_with_30 = vec![4, 6, 9, 11];
+    let month_with_30 = [4, 6, 9, 11];
 
     let year = cap_dt["year"].parse::<u32>().unwrap();
     ensure!(
@@ -187,36 +198,36 @@ fn parse_date(arg: &str) -> Result<Datetime> {
         DatetimeError::ParsingError(String::from("Day can be in range of 1 to 31"))
     );
     ensure!(
-        month_with_30.contains(&month) && day == 31,
-        DatetimeError::ParsingError(String::from(format!(
+        !(month_with_30.contains(&month) && day == 31),
+        DatetimeError::ParsingError(format!(
             "{} can't have 31 days",
-            get_month_name_from_index(month as u8)
-        )))
+            get_month_name_from_index(month)
+        ))
     );
     ensure!(
-        month == 2 && day > 29,
-        DatetimeError::ParsingError(String::from(format!(
+        !(month == 2 && day > 29),
+        DatetimeError::ParsingError(format!(
             "{} can't have more than 29 days",
-            get_month_name_from_index(month as u8)
-        )))
+            get_month_name_from_index(month)
+        ))
     );
     ensure!(
-        month == 2 && day == 29 && !is_leap_year(year),
-        DatetimeError::ParsingError(String::from(format!("{} is not leap year", &year)))
+        !(month == 2 && day == 29 && !is_leap_year(year)),
+        DatetimeError::ParsingError(format!("{} is not leap year", &year))
     );
     let hour = cap_dt["hour"].parse::<u8>().unwrap();
     ensure!(
-        hour > 23,
+        hour <= 23,
         DatetimeError::ParsingError(String::from("Hour ranges from 0 to 23"))
     );
     let minute = cap_dt["min"].parse::<u8>().unwrap();
     ensure!(
-        hour > 59,
+        minute <= 59,
         DatetimeError::ParsingError(String::from("Minute ranges from 0 to 59"))
     );
     let second = cap_dt["sec"].parse::<u8>().unwrap();
     ensure!(
-        hour > 59,
+        second <= 59,
         DatetimeError::ParsingError(String::from("Second ranges from 0 to 59"))
     );
 
@@ -230,19 +241,143 @@ fn parse_date(arg: &str) -> Result<Datetime> {
     })
 }
 
+/// Parse `yyyymmddhhmmss`, the form `Datetime::digits` gives
+pub fn parse_digits(arg: &str) -> Result<Datetime> {
+    ensure!(
+        arg.len() == 14 && arg.bytes().all(|b| b.is_ascii_digit()),
+        DatetimeError::ParsingError(format!("Invalid datetime '{}', expected yyyymmddhhmmss", arg))
+    );
+    parse_date(&format!(
+        "{}-{}-{} {}:{}:{}",
+        &arg[..4],
+        &arg[4..6],
+        &arg[6..8],
+        &arg[8..10],
+        &arg[10..12],
+        &arg[12..]
+    ))
+}
+
+impl Datetime {
+    /// Spelled out form, like `5 August 2024, 14:03:09 UTC`
+    pub fn readable(&self) -> String {
+        format!(
+            "{} {} {}, {:02}:{:02}:{:02} UTC",
+            self.day,
+            get_month_name_from_index(self.month),
+            self.year,
+            self.hour,
+            self.minute,
+            self.second
+        )
+    }
+
+    /// Form used by Atom and most web formats, like `2024-08-05T14:03:09Z`
+    pub fn rfc3339(&self) -> String {
+        format!(
+            "{}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+
+    /// Seconds since the unix epoch, the inverse of `get_datetime_for_epochs`
+    pub fn epochs(&self) -> u64 {
+        let days_in_years = (1970..self.year).map(|y| if is_leap_year(y) { 366 } else { 365 }).sum::<u64>();
+        let days_in_months = (1..self.month)
+            .map(|m| match m {
+                2 if is_leap_year(self.year) => 29,
+                2 => 28,
+                4 | 6 | 9 | 11 => 30,
+                _ => 31,
+            })
+            .sum::<u64>();
+        let days = days_in_years + days_in_months + self.day as u64 - 1;
+        days * 86400 + self.hour as u64 * 3600 + self.minute as u64 * 60 + self.second as u64
+    }
+
+    /// Digits only, like `20240805140309`, the form web archives put in their URLs
+    pub fn digits(&self) -> String {
+        format!(
+            "{}{:02}{:02}{:02}{:02}{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+
+    /// Form used by RSS and email, like `Mon, 05 Aug 2024 14:03:09 +0000`
+    pub fn rfc2822(&self) -> String {
+        // Sakamoto's method, 0 being Sunday
+        let offsets = [0, 3, 2, 5, 0, 3, 5, 1, 4, 6, 2, 4];
+        let year = if self.month < 3 { self.year - 1 } else { self.year };
+        let weekday = (year + year / 4 - year / 100 + year / 400 + offsets[self.month as usize - 1] + self.day as u32) % 7;
+        format!(
+            "{}, {:02} {} {} {:02}:{:02}:{:02} +0000",
+            ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"][weekday as usize],
+            self.day,
+            &get_month_name_from_index(self.month)[..3],
+            self.year,
+            self.hour,
+            self.minute,
+            self.second
+        )
+    }
+}
 
 #[allow(dead_code)]
 pub fn get_current_datetime() -> Datetime {
     let today = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .expect("Duration befor Unix Epoch");
-    return get_datetime_for_epochs(today.as_secs());
+    get_datetime_for_epochs(today.as_secs())
+}
+
+/// Parse a span like `30d` or `2w` into seconds
+/// Supported units are `s`, `m`, `h`, `d` and `w`
+pub fn parse_duration(arg: &str) -> Result<u64> {
+    let re = Regex::new(r"^(?P<count>\d+)(?P<unit>[smhdw])$").expect("Failed to create regex");
+    let Some(cap) = re.captures(arg.trim()) else {
+        return Err(DatetimeError::ParsingError(format!(
+            "Invalid duration '{}', expected something like 30d or 2w",
+            arg
+        ))
+        .into());
+    };
+    let count = cap["count"].parse::<u64>()?;
+    let unit = match &cap["unit"] {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => 7 * 86400,
+    };
+
+    count.checked_mul(unit).ok_or_else(|| DatetimeError::ParsingError(format!("Duration '{}' is too long", arg)).into())
+}
+
+/// Last second a `Datetime` can hold, `9999-12-31 23:59:59`
+const MAX_EPOCHS: u64 = 253_402_300_799;
+
+/// Parse when something should happen, either `in <duration>` from now, like `in 2w` (see
+/// `parse_duration`), or `on <yyyy-mm-dd>`, at the start of that day
+pub fn parse_when(arg: &str) -> Result<Datetime> {
+    match arg.trim().split_once(char::is_whitespace) {
+        Some(("in", duration)) => {
+            let epochs = get_current_datetime().epochs().checked_add(parse_duration(duration)?).filter(|e| *e <= MAX_EPOCHS);
+            let epochs = epochs.ok_or_else(|| DatetimeError::ParsingError(format!("Time '{}' is too far away", arg)))?;
+            Ok(get_datetime_for_epochs(epochs))
+        }
+        Some(("on", day)) => parse_date(&format!("{} 00:00:00", day.trim())),
+        _ => Err(DatetimeError::ParsingError(format!(
+            "Invalid time '{}', expected something like 'in 2w' or 'on 2025-01-10'",
+            arg
+        ))
+        .into()),
+    }
 }
 
 #[test]
 fn test_datetime_from_epoch() {
     let epochs: Vec<u64> = vec![946684800, 1609459199, 253402300799, 0, 1582934400];
-    let res_datetime = vec![
+    let res_datetime = [
         "2000-01-01 00:00:00",
         "2020-12-31 23:59:59",
         "9999-12-31 23:59:59",
@@ -260,3 +395,61 @@ fn test_datetime_from_epoch() {
         assert_eq!(res_datetime[i], &output_datetime[i]);
     }
 }
+
+#[test]
+fn test_datetime_from_webkit_time() {
+    assert_eq!(format!("{}", get_datetime_for_webkit_time(13_253_932_799_000_000)), "2020-12-31 23:59:59");
+    assert_eq!(format!("{}", get_datetime_for_webkit_time(0)), "1970-01-01 00:00:00");
+}
+
+#[test]
+fn test_parse_duration() -> Result<()> {
+    assert_eq!(parse_duration("45s")?, 45);
+    assert_eq!(parse_duration("30d")?, 30 * 86400);
+    assert_eq!(parse_duration("2w")?, 14 * 86400);
+    assert!(parse_duration("2 weeks").is_err());
+    assert!(parse_duration("999999999999999d").is_err());
+    assert!(parse_duration("99999999999999999999s").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_date() -> Result<()> {
+    let datetime = parse_date("2024-08-05 14:03:09")?;
+    assert_eq!(format!("{}", datetime), "2024-08-05 14:03:09");
+    assert_eq!(datetime.readable(), "5 August 2024, 14:03:09 UTC");
+
+    assert!(parse_date("2023-02-29 00:00:00").is_err());
+    assert!(parse_date("2024-04-31 00:00:00").is_err());
+    assert!(parse_date("2024-01-01 24:00:00").is_err());
+    assert!(parse_date("2024-01-01").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_feed_formats() -> Result<()> {
+    let datetime = parse_date("2024-08-05 14:03:09")?;
+    assert_eq!(datetime.rfc3339(), "2024-08-05T14:03:09Z");
+    assert_eq!(datetime.rfc2822(), "Mon, 05 Aug 2024 14:03:09 +0000");
+    assert_eq!(parse_date("2000-02-29 00:00:00")?.rfc2822(), "Tue, 29 Feb 2000 00:00:00 +0000");
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_when() -> Result<()> {
+    for epoch in [0, 951782400, 1609459199, 1735776000] {
+        assert_eq!(get_datetime_for_epochs(epoch).epochs(), epoch);
+    }
+    assert_eq!(format!("{}", parse_when("on 2025-01-10")?), "2025-01-10 00:00:00");
+    let in_two_weeks = parse_when("in 2w")?.epochs();
+    assert!(in_two_weeks.abs_diff(get_current_datetime().epochs() + 14 * 86400) <= 1);
+    assert!(parse_when("2w").is_err());
+    assert!(parse_when("on 2025-02-30").is_err());
+    assert!(parse_when("in 999999999999999d").is_err());
+    assert!(parse_when("in 500000w").is_err());
+
+    Ok(())
+}
@@ -0,0 +1,410 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{date, favicon::Icon, Bookmark};
+
+/// Layout version of the json and jsonl dumps, bumped whenever a field changes meaning.
+/// Fields added to `Bookmark` are picked up without a bump, older dumps just lack them.
+pub const DUMP_VERSION: u32 = 1;
+
+const DUMP_FORMAT: &str = "bmark";
+
+/// Envelope opening every dump
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Header {
+    pub format: String,
+    pub version: u32,
+    pub exported_at: String,
+}
+
+impl Header {
+    fn new() -> Self {
+        Header {
+            format: DUMP_FORMAT.to_owned(),
+            version: DUMP_VERSION,
+            exported_at: format!("{}", date::get_current_datetime()),
+        }
+    }
+
+    /// Make sure the dump is ours and not from a newer version
+    pub fn check(&self) -> Result<()> {
+        ensure!(self.format == DUMP_FORMAT, "Not a bmark dump, format is {:?}", self.format);
+        ensure!(
+            self.version <= DUMP_VERSION,
+            "Dump has version {}, this bmark only reads up to {}",
+            self.version,
+            DUMP_VERSION
+        );
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+struct DumpOut<'a> {
+    #[serde(flatten)]
+    header: Header,
+    bookmarks: &'a [Bookmark],
+}
+
+/// A json dump read back
+#[derive(Deserialize)]
+pub struct Dump {
+    #[serde(flatten)]
+    pub header: Header,
+    pub bookmarks: Vec<Bookmark>,
+}
+
+/// Write the bookmarks as a single json document
+pub fn json<W: Write>(bookmarks: &[Bookmark], mut out: W) -> Result<()> {
+    let dump = DumpOut {
+        header: Header::new(),
+        bookmarks,
+    };
+    serde_json::to_writer_pretty(&mut out, &dump)?;
+    writeln!(out)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Write the header on the first line and then a bookmark per line
+pub fn jsonl<W: Write>(bookmarks: &[Bookmark], mut out: W) -> Result<()> {
+    serde_json::to_writer(&mut out, &Header::new())?;
+    writeln!(out)?;
+    for bookmark in bookmarks {
+        serde_json::to_writer(&mut out, bookmark)?;
+        writeln!(out)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+#[derive(Clone, Copy)]
+pub enum GroupBy {
+    Category,
+    Tag,
+}
+
+/// `[name](url)` with the characters markdown would trip on escaped
+fn link(b: &Bookmark) -> String {
+    let text = b.name.as_deref().filter(|n| !n.is_empty()).unwrap_or(&b.url);
+    let text = text.replace('\\', "\\\\").replace('[', "\\[").replace(']', "\\]");
+    if b.url.contains([' ', '(', ')']) {
+        format!("[{}](<{}>)", text, b.url)
+    } else {
+        format!("[{}]({})", text, b.url)
+    }
+}
+
+/// Tags as `#hashtags`, which can't hold spaces
+fn hashtags(tags: &[String]) -> String {
+    tags.iter()
+        .map(|t| format!("#{}", t.split_whitespace().collect::<Vec<_>>().join("-")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Write a markdown list of the bookmarks under a heading per category or per tag. With tags, a
+/// bookmark is listed under each of its tags. Icons found in `icons` by the bookmark's URL are
+/// embedded in front of its link.
+pub fn markdown<W: Write>(bookmarks: &[Bookmark], group_by: GroupBy, icons: &HashMap<String, Icon>, mut out: W) -> Result<()> {
+    let mut groups: BTreeMap<String, Vec<&Bookmark>> = BTreeMap::new();
+    for b in bookmarks {
+        match group_by {
+            GroupBy::Category => {
+                let category = b.category.clone().unwrap_or_else(|| String::from("Uncategorized"));
+                groups.entry(category).or_default().push(b);
+            }
+            GroupBy::Tag if b.tags.is_empty() => groups.entry(String::from("Untagged")).or_default().push(b),
+            GroupBy::Tag => {
+                for tag in &b.tags {
+                    groups.entry(tag.clone()).or_default().push(b);
+                }
+            }
+        }
+    }
+
+    writeln!(out, "# Bookmarks")?;
+    for (group, bookmarks) in groups {
+        writeln!(out, "\n## {}\n", group)?;
+        for b in bookmarks {
+            let icon = icons.get(&b.url).map(|icon| format!("![]({}) ", icon.data_uri())).unwrap_or_default();
+            let mut entry = format!("- {}{}", icon, link(b));
+            if let Some(desc) = b.desc.as_deref().filter(|d| !d.is_empty()) {
+                entry.push_str(&format!(" — {}", desc.lines().collect::<Vec<_>>().join(" ")));
+            }
+            if !b.tags.is_empty() {
+                entry.push_str(&format!(" {}", hashtags(&b.tags)));
+            }
+            // notes are markdown already, quoted under the entry they keep their formatting
+            for note in &b.notes {
+                entry.push('\n');
+                for line in note.body.lines() {
+                    entry.push_str(format!("\n  > {}", line).trim_end());
+                }
+            }
+            writeln!(out, "{}", entry)?;
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// How many notes `vault` wrote
+#[derive(Debug, Default)]
+pub struct VaultReport {
+    pub created: usize,
+    pub updated: usize,
+}
+
+/// Json strings are valid yaml double quoted scalars, which spares escaping by hand
+fn yaml_string(value: &str) -> String {
+    serde_json::to_string(value).expect("Strings always serialize")
+}
+
+fn frontmatter(b: &Bookmark) -> String {
+    let mut lines = vec![
+        String::from("---"),
+        format!("bmark_id: {}", yaml_string(&b.id)),
+        format!("url: {}", yaml_string(&b.url)),
+        format!("tags: [{}]", b.tags.iter().map(|t| yaml_string(t)).collect::<Vec<_>>().join(", ")),
+    ];
+    if let Some(category) = &b.category {
+        lines.push(format!("category: {}", yaml_string(category)));
+    }
+    lines.push(format!("added_at: {}", yaml_string(&b.added_at)));
+    lines.push(String::from("---\n"));
+    lines.join("\n")
+}
+
+/// Split a note into its frontmatter (markers included) and body
+fn split_note(content: &str) -> (&str, &str) {
+    if let Some(rest) = content.strip_prefix("---\n") {
+        if let Some(end) = rest.find("\n---\n") {
+            let split = 4 + end + 5;
+            return (&content[..split], &content[split..]);
+        }
+    }
+    ("", content)
+}
+
+/// Id of the bookmark a note was exported from, read out of its frontmatter
+fn note_bmark_id(content: &str) -> Option<String> {
+    let (frontmatter, _) = split_note(content);
+    frontmatter
+        .lines()
+        .find_map(|l| l.strip_prefix("bmark_id:"))
+        .and_then(|id| serde_json::from_str(id.trim()).ok())
+}
+
+const NOTES_START: &str = "<!-- bmark notes -->\n";
+const NOTES_END: &str = "<!-- /bmark notes -->\n";
+
+/// The bookmark's own notes, between markers so exporting again can tell them from what was
+/// written in the vault. Empty when it has none.
+fn notes_section(b: &Bookmark) -> String {
+    if b.notes.is_empty() {
+        return String::new();
+    }
+    let mut section = format!("{}## Notes\n", NOTES_START);
+    for note in &b.notes {
+        let edited = note.updated_at.as_ref().map(|at| format!(", edited {}", at)).unwrap_or_default();
+        section.push_str(&format!("\n### Note {} ({}{})\n\n{}\n", note.seq, note.created_at, edited, note.body.trim_end()));
+    }
+    section.push_str(NOTES_END);
+    section
+}
+
+/// `body` with its notes section swapped for the bookmark's current notes, or with them added
+/// at the end when it has no section yet
+fn with_notes(body: &str, b: &Bookmark) -> String {
+    let notes = notes_section(b);
+    if let Some(start) = body.find(NOTES_START) {
+        if let Some(end) = body[start..].find(NOTES_END) {
+            let end = start + end + NOTES_END.len();
+            return format!("{}{}{}", &body[..start], notes, &body[end..]);
+        }
+    }
+    if notes.is_empty() {
+        return String::from(body);
+    }
+    let gap = if body.is_empty() || body.ends_with("\n\n") { "" } else if body.ends_with('\n') { "\n" } else { "\n\n" };
+    format!("{}{}{}", body, gap, notes)
+}
+
+/// File name for a new note without its extension, from the bookmark's name
+fn note_file_stem(b: &Bookmark) -> String {
+    let name = b.name.as_deref().filter(|n| !n.trim().is_empty()).unwrap_or(&b.url);
+    let stem = name
+        .chars()
+        .map(|c| if c.is_alphanumeric() || " -_".contains(c) { c } else { '-' })
+        .collect::<String>();
+    stem.trim().chars().take(80).collect::<String>()
+}
+
+/// Write a note per bookmark into `dir`, an Obsidian vault or a folder in one. Notes are found
+/// again by the `bmark_id` in their frontmatter, so exporting again only rewrites the
+/// frontmatter and the bookmark's notes section of existing notes and keeps whatever else was
+/// written in them.
+pub fn vault(bookmarks: &[Bookmark], dir: &Path) -> Result<VaultReport> {
+    fs::create_dir_all(dir).with_context(|| format!("Couldn't create {}", dir.display()))?;
+
+    let mut existing: HashMap<String, PathBuf> = HashMap::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|e| e == "md") {
+            if let Some(id) = fs::read_to_string(&path).ok().as_deref().and_then(note_bmark_id) {
+                existing.insert(id, path);
+            }
+        }
+    }
+
+    let mut report = VaultReport::default();
+    for b in bookmarks {
+        if let Some(path) = existing.get(&b.id) {
+            let content = fs::read_to_string(path)?;
+            let (_, body) = split_note(&content);
+            fs::write(path, format!("{}{}", frontmatter(b), with_notes(body, b)))?;
+            report.updated += 1;
+            continue;
+        }
+
+        let stem = note_file_stem(b);
+        let mut path = dir.join(format!("{}.md", stem));
+        // other notes may have this name already, number this one the way Obsidian does
+        let mut n = 1;
+        while path.exists() {
+            n += 1;
+            path = dir.join(format!("{} {}.md", stem, n));
+        }
+        let mut body = format!("\n# {}\n\n<{}>\n", b.name.as_deref().unwrap_or(&b.url), b.url);
+        if let Some(desc) = &b.desc {
+            body.push_str(&format!("\n{}\n", desc));
+        }
+        let body = with_notes(&body, b);
+        fs::write(&path, format!("{}{}", frontmatter(b), body))?;
+        report.created += 1;
+    }
+    Ok(report)
+}
+
+#[test]
+fn markdown_groups_by_tag() -> Result<()> {
+    let bookmarks = vec![
+        Bookmark {
+            url: "https://docs.rs/".into(),
+            name: Some("Docs [rs]".into()),
+            desc: Some("Crate docs".into()),
+            tags: vec!["rust".into(), "web docs".into()],
+            ..Default::default()
+        },
+        Bookmark {
+            url: "https://example.com/a b".into(),
+            notes: vec![crate::Note { seq: 1, body: "Read *twice*\n\n- then compare".into(), ..Default::default() }],
+            ..Default::default()
+        },
+    ];
+
+    let mut out = vec![];
+    let icons = HashMap::from([(
+        String::from("https://docs.rs/"),
+        Icon { bytes: b"gif".to_vec(), mime: String::from("image/gif") },
+    )]);
+    markdown(&bookmarks, GroupBy::Tag, &icons, &mut out)?;
+    let expected = "# Bookmarks\n\n\
+                    ## Untagged\n\n- [https://example.com/a b](<https://example.com/a b>)\n\n  > Read *twice*\n  >\n  > - then compare\n\n\
+                    ## rust\n\n- ![](data:image/gif;base64,Z2lm) [Docs \\[rs\\]](https://docs.rs/) — Crate docs #rust #web-docs\n\n\
+                    ## web docs\n\n- ![](data:image/gif;base64,Z2lm) [Docs \\[rs\\]](https://docs.rs/) — Crate docs #rust #web-docs\n";
+    assert_eq!(String::from_utf8(out)?, expected);
+
+    Ok(())
+}
+
+#[test]
+fn vault_updates_notes_in_place() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("bmark-vault-test-{}", std::process::id()));
+    _ = fs::remove_dir_all(&dir);
+    let mut bookmark = Bookmark {
+        id: "01910f6a-5fd6-7a3c-9c2e-0a1b2c3d4e5f".into(),
+        url: "https://docs.rs/".into(),
+        name: Some("Docs".into()),
+        added_at: "2020-12-31 23:59:59".into(),
+        ..Default::default()
+    };
+
+    let report = vault(std::slice::from_ref(&bookmark), &dir)?;
+    assert_eq!((report.created, report.updated), (1, 0));
+    let note = dir.join("Docs.md");
+    fs::write(&note, format!("{}\nMy own thoughts\n", fs::read_to_string(&note)?))?;
+
+    bookmark.name = Some("Docs.rs".into());
+    bookmark.tags = vec!["rust".into()];
+    let report = vault(std::slice::from_ref(&bookmark), &dir)?;
+    assert_eq!((report.created, report.updated), (0, 1));
+    let content = fs::read_to_string(&note)?;
+    assert!(content.starts_with("---\nbmark_id: \"01910f6a-5fd6-7a3c-9c2e-0a1b2c3d4e5f\"\nurl: \"https://docs.rs/\"\ntags: [\"rust\"]\n"));
+    assert!(content.ends_with("My own thoughts\n"));
+
+    // the bookmark's notes are added below, and replaced rather than repeated on the next export
+    bookmark.notes = vec![crate::Note { seq: 1, body: "Check the *search*".into(), created_at: "2021-01-02 03:04:05".into(), updated_at: None }];
+    vault(std::slice::from_ref(&bookmark), &dir)?;
+    bookmark.notes[0].body = "Check the search\n\nand the source view".into();
+    bookmark.notes[0].updated_at = Some("2021-02-03 04:05:06".into());
+    vault(std::slice::from_ref(&bookmark), &dir)?;
+    let content = fs::read_to_string(&note)?;
+    assert!(content.ends_with(
+        "My own thoughts\n\n<!-- bmark notes -->\n## Notes\n\n\
+         ### Note 1 (2021-01-02 03:04:05, edited 2021-02-03 04:05:06)\n\nCheck the search\n\nand the source view\n<!-- /bmark notes -->\n"
+    ));
+    assert_eq!(content.matches("## Notes").count(), 1);
+
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn vault_numbers_notes_with_the_same_name() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("bmark-vault-names-test-{}", std::process::id()));
+    _ = fs::remove_dir_all(&dir);
+    let bookmarks = (1..=3)
+        .map(|i| Bookmark {
+            id: format!("01910f6a-5fd6-7a3c-9c2e-0a1b2c3d4e5{}", i),
+            url: format!("https://example.com/{}", i),
+            name: Some("Same".into()),
+            added_at: "2020-12-31 23:59:59".into(),
+            ..Default::default()
+        })
+        .collect::<Vec<_>>();
+
+    let report = vault(&bookmarks, &dir)?;
+    assert_eq!((report.created, report.updated), (3, 0));
+    for (name, b) in ["Same.md", "Same 2.md", "Same 3.md"].iter().zip(&bookmarks) {
+        assert_eq!(note_bmark_id(&fs::read_to_string(dir.join(name))?), Some(b.id.clone()));
+    }
+
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn buffered_write_errors_are_returned() {
+    struct Full;
+    impl Write for Full {
+        fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::new(std::io::ErrorKind::StorageFull, "disk full"))
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    assert!(json(&[], std::io::BufWriter::new(Full)).is_err());
+    assert!(jsonl(&[], std::io::BufWriter::new(Full)).is_err());
+    assert!(markdown(&[], GroupBy::Tag, &HashMap::new(), std::io::BufWriter::new(Full)).is_err());
+}
@@ -0,0 +1,164 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use regex::Regex;
+
+use crate::fetch::{self, FetchOptions};
+
+#[derive(Clone, Copy)]
+pub struct FaviconOptions {
+    /// How long a cached icon, or the lack of one, is trusted before asking the site again
+    pub max_age: Duration,
+    pub fetch: FetchOptions,
+}
+
+impl Default for FaviconOptions {
+    fn default() -> Self {
+        FaviconOptions {
+            max_age: Duration::from_secs(30 * 24 * 60 * 60),
+            fetch: FetchOptions { max_bytes: 512 * 1024, ..Default::default() },
+        }
+    }
+}
+
+/// A site's icon
+#[derive(Debug, Clone, PartialEq)]
+pub struct Icon {
+    pub bytes: Vec<u8>,
+    pub mime: String,
+}
+
+impl Icon {
+    /// The icon as a `data:` URI, to embed in pages which should show it without a network
+    pub fn data_uri(&self) -> String {
+        format!("data:{};base64,{}", self.mime, STANDARD.encode(&self.bytes))
+    }
+}
+
+/// Image types an icon is kept as when the server says so. Icons end up in `data:` URIs of
+/// exported pages, so whatever else a server claims isn't taken as is.
+const IMAGE_TYPES: &[&str] = &[
+    "image/png",
+    "image/x-icon",
+    "image/vnd.microsoft.icon",
+    "image/gif",
+    "image/jpeg",
+    "image/webp",
+    "image/svg+xml",
+    "image/avif",
+    "image/bmp",
+];
+
+pub(crate) fn is_image_type(mime: &str) -> bool {
+    IMAGE_TYPES.contains(&mime)
+}
+
+/// Image type of `bytes`, from the content type the server gave or else from the bytes
+/// themselves, since servers often send icons as `application/octet-stream` or `text/plain`.
+/// None when it isn't an image, like the html of a page served for every missing file.
+fn mime(content_type: &str, bytes: &[u8]) -> Option<String> {
+    let content_type = content_type.split(';').next().unwrap_or_default().trim().to_lowercase();
+    if is_image_type(&content_type) {
+        return Some(content_type);
+    }
+    let sniffed = if bytes.starts_with(b"\x89PNG") {
+        "image/png"
+    } else if bytes.starts_with(&[0, 0, 1, 0]) {
+        "image/x-icon"
+    } else if bytes.starts_with(b"GIF8") {
+        "image/gif"
+    } else if bytes.starts_with(&[0xff, 0xd8, 0xff]) {
+        "image/jpeg"
+    } else if bytes.len() > 12 && bytes.starts_with(b"RIFF") && &bytes[8..12] == b"WEBP" {
+        "image/webp"
+    } else if String::from_utf8_lossy(&bytes[..bytes.len().min(512)]).contains("<svg") {
+        "image/svg+xml"
+    } else {
+        return None;
+    };
+    Some(String::from(sniffed))
+}
+
+/// Icons the page links to, the plain `icon` ones before the `apple-touch-icon` ones
+fn icon_links(url: &str, html: &str) -> Vec<String> {
+    let link_re = Regex::new(r"(?is)<link\b[^>]*>").expect("Failed to create regex");
+    let mut links = vec![];
+    for m in link_re.find_iter(html) {
+        let attrs = fetch::attributes(m.as_str());
+        let attr = |name: &str| attrs.iter().find(|(n, _)| n == name).map(|(_, v)| v.trim());
+        let Some(href) = attr("href").filter(|h| !h.is_empty() && !h.starts_with("data:")) else { continue };
+        let rel = attr("rel").unwrap_or_default().to_lowercase();
+        let rels = rel.split_whitespace().collect::<Vec<_>>();
+        let rank = if rels.contains(&"icon") {
+            0
+        } else if rels.iter().any(|r| r.starts_with("apple-touch-icon")) {
+            1
+        } else {
+            continue;
+        };
+        links.push((rank, fetch::resolve_url(url, href)));
+    }
+    links.sort_by_key(|(rank, _)| *rank);
+    links.into_iter().map(|(_, link)| link).collect()
+}
+
+/// Find the icon of the site `url` is on: the one its page links to with `<link rel=icon>`,
+/// else `/favicon.ico`. None when the site has no icon; failing means the site couldn't be
+/// asked at all, so the answer is worth asking for again later.
+pub fn resolve(url: &str, options: &FetchOptions) -> Result<Option<Icon>> {
+    let page = fetch::get(url, options);
+    let mut candidates = match &page {
+        Ok(page) if page.content_type.contains("html") => icon_links(&page.url, &page.text()),
+        _ => vec![],
+    };
+    let base = page.as_ref().map_or(url, |page| page.url.as_str());
+    candidates.push(fetch::resolve_url(base, "/favicon.ico"));
+
+    let mut fallback = None;
+    for candidate in candidates {
+        match fetch::get(&candidate, options) {
+            Ok(found) => {
+                if let Some(mime) = mime(&found.content_type, &found.body) {
+                    return Ok(Some(Icon { bytes: found.body, mime }));
+                }
+            }
+            Err(e) => fallback = Some(e),
+        }
+    }
+    match (page, fallback) {
+        (Err(e), Some(_)) => Err(e),
+        _ => Ok(None),
+    }
+}
+
+#[test]
+fn prefers_linked_icon_over_favicon_ico() -> Result<()> {
+    use crate::fetch::{response, serve};
+
+    let base = serve(|path| match path {
+        "/linked" => response(
+            "200 OK",
+            &[("Content-Type", "text/html")],
+            "<link rel=\"apple-touch-icon\" href=\"/touch.png\"><link href=\"/missing.png\" rel=\"icon\">\
+             <link rel=\"shortcut icon\" href=\"static/icon.svg\">",
+        ),
+        "/plain" => response("200 OK", &[("Content-Type", "text/html")], "<title>No icon here</title>"),
+        "/static/icon.svg" => response("200 OK", &[("Content-Type", "text/plain")], "<svg xmlns=\"http://www.w3.org/2000/svg\"/>"),
+        "/favicon.ico" => response("200 OK", &[], "\0\0\x01\0ico"),
+        "/odd" => response("200 OK", &[("Content-Type", "text/html")], "<link rel=\"icon\" href=\"/odd.gif\">"),
+        "/odd.gif" => response("200 OK", &[("Content-Type", "image/png\" onerror=\"alert(1)")], "GIF89a"),
+        _ => response("404 Not Found", &[("Content-Type", "text/html")], "<html>Not found</html>"),
+    });
+    let options = FetchOptions::default();
+
+    let icon = resolve(&format!("{}/linked", base), &options)?.unwrap();
+    assert_eq!(icon.mime, "image/svg+xml");
+    let icon = resolve(&format!("{}/plain", base), &options)?.unwrap();
+    assert_eq!(icon, Icon { bytes: b"\0\0\x01\0ico".to_vec(), mime: String::from("image/x-icon") });
+    assert_eq!(icon.data_uri(), "data:image/x-icon;base64,AAABAGljbw==");
+    // a type which isn't a known image type is told from the bytes instead
+    assert_eq!(resolve(&format!("{}/odd", base), &options)?.unwrap().mime, "image/gif");
+    assert!(resolve("http://127.0.0.1:1/", &options).is_err());
+    Ok(())
+}
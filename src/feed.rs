@@ -0,0 +1,198 @@
+use std::io::Write;
+
+use anyhow::{Context, Result};
+
+use crate::{date, publish::escape_html, Bookmark, LinkFilter, ListOptions, TagMode};
+
+#[derive(Clone, Copy)]
+pub enum FeedFormat {
+    Atom,
+    Rss,
+}
+
+/// What a feed is about, the bookmarks in it aside
+pub struct Feed<'a> {
+    pub title: &'a str,
+    /// Stays the same for as long as the feed picks the same bookmarks, see `feed_id`
+    pub id: String,
+    /// Page the feed belongs to, if it's published anywhere. RSS feeds can't go without one.
+    pub link: Option<&'a str>,
+}
+
+/// What a feed of the bookmarks picked by `options` and the search `query` is about, like
+/// `Bookmarks tagged rust or web, pinned`. Every filter shows, so feeds picking different
+/// bookmarks get different titles and ids. Tags are sorted, so the feed keeps its title and its id
+/// whichever order they are given in.
+pub fn selection(options: &ListOptions, query: Option<&str>) -> String {
+    let mut selection = vec![];
+    if !options.tags.is_empty() {
+        let mut tags = options.tags.iter().map(String::as_str).collect::<Vec<_>>();
+        tags.sort_unstable();
+        tags.dedup();
+        let separator = if matches!(options.tag_mode, TagMode::All) { " and " } else { " or " };
+        selection.push(format!("tagged {}", tags.join(separator)));
+    }
+    if let Some(category) = &options.category {
+        selection.push(format!("in {}", category));
+    }
+    if let Some(status) = options.status {
+        selection.push(String::from(status.as_str()));
+    }
+    if options.pinned {
+        selection.push(String::from("pinned"));
+    }
+    if let Some(rating) = options.min_rating {
+        selection.push(format!("rated {} or more", rating));
+    }
+    match options.link {
+        Some(LinkFilter::Broken) => selection.push(String::from("with broken links")),
+        Some(LinkFilter::Redirected) => selection.push(String::from("with redirected links")),
+        None => {}
+    }
+    if let Some(query) = query {
+        selection.push(format!("matching {}", query));
+    }
+    format!("Bookmarks {}", selection.join(", ")).trim().to_owned()
+}
+
+/// Id for the feed of bookmarks picked by `selection`, like `tag rust`
+pub fn feed_id(selection: &str) -> String {
+    format!("urn:bmark:feed:{}", crate::publish::slug(selection))
+}
+
+/// Newest first, which is how readers expect feeds
+fn newest_first(bookmarks: &[Bookmark]) -> Vec<&Bookmark> {
+    let mut sorted = bookmarks.iter().collect::<Vec<_>>();
+    sorted.sort_by(|a, b| b.added_at.cmp(&a.added_at).then_with(|| b.id.cmp(&a.id)));
+    sorted
+}
+
+fn added_at(b: &Bookmark) -> date::Datetime {
+    date::parse_date(&b.added_at).unwrap_or_else(|_| date::get_current_datetime())
+}
+
+pub fn write<W: Write>(format: FeedFormat, feed: &Feed, bookmarks: &[Bookmark], out: W) -> Result<()> {
+    match format {
+        FeedFormat::Atom => atom(feed, bookmarks, out),
+        FeedFormat::Rss => rss(feed, bookmarks, out),
+    }
+}
+
+/// Write an Atom 1.0 feed, entries identified by the `urn:uuid:` of the bookmark
+pub fn atom<W: Write>(feed: &Feed, bookmarks: &[Bookmark], mut out: W) -> Result<()> {
+    let bookmarks = newest_first(bookmarks);
+    let updated = bookmarks.first().map(|b| added_at(b)).unwrap_or_else(date::get_current_datetime);
+
+    writeln!(out, "<?xml version=\"1.0\" encoding=\"utf-8\"?>")?;
+    writeln!(out, "<feed xmlns=\"http://www.w3.org/2005/Atom\">")?;
+    writeln!(out, "  <title>{}</title>", escape_html(feed.title))?;
+    writeln!(out, "  <id>{}</id>", escape_html(&feed.id))?;
+    writeln!(out, "  <updated>{}</updated>", updated.rfc3339())?;
+    if let Some(link) = feed.link {
+        writeln!(out, "  <link href=\"{}\"/>", escape_html(link))?;
+    }
+    writeln!(out, "  <author><name>bmark</name></author>")?;
+    writeln!(out, "  <generator>bmark</generator>")?;
+    for b in bookmarks {
+        let added_at = added_at(b).rfc3339();
+        writeln!(out, "  <entry>")?;
+        writeln!(out, "    <title>{}</title>", escape_html(b.name.as_deref().unwrap_or(&b.url)))?;
+        writeln!(out, "    <link href=\"{}\"/>", escape_html(&b.url))?;
+        writeln!(out, "    <id>urn:uuid:{}</id>", b.id)?;
+        writeln!(out, "    <published>{}</published>", added_at)?;
+        writeln!(out, "    <updated>{}</updated>", added_at)?;
+        if let Some(desc) = &b.desc {
+            writeln!(out, "    <summary>{}</summary>", escape_html(desc))?;
+        }
+        for tag in &b.tags {
+            writeln!(out, "    <category term=\"{}\"/>", escape_html(tag))?;
+        }
+        writeln!(out, "  </entry>")?;
+    }
+    writeln!(out, "</feed>")?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Write an RSS 2.0 feed, items identified by the `urn:uuid:` of the bookmark
+pub fn rss<W: Write>(feed: &Feed, bookmarks: &[Bookmark], mut out: W) -> Result<()> {
+    // RSS 2.0 requires the channel to link to its page
+    let link = feed.link.context("An RSS feed needs the link of the page it belongs to")?;
+    let bookmarks = newest_first(bookmarks);
+    let updated = bookmarks.first().map(|b| added_at(b)).unwrap_or_else(date::get_current_datetime);
+
+    writeln!(out, "<?xml version=\"1.0\" encoding=\"utf-8\"?>")?;
+    writeln!(out, "<rss version=\"2.0\">")?;
+    writeln!(out, "  <channel>")?;
+    writeln!(out, "    <title>{}</title>", escape_html(feed.title))?;
+    writeln!(out, "    <link>{}</link>", escape_html(link))?;
+    writeln!(out, "    <description>{}</description>", escape_html(feed.title))?;
+    writeln!(out, "    <lastBuildDate>{}</lastBuildDate>", updated.rfc2822())?;
+    writeln!(out, "    <generator>bmark</generator>")?;
+    for b in bookmarks {
+        writeln!(out, "    <item>")?;
+        writeln!(out, "      <title>{}</title>", escape_html(b.name.as_deref().unwrap_or(&b.url)))?;
+        writeln!(out, "      <link>{}</link>", escape_html(&b.url))?;
+        writeln!(out, "      <guid isPermaLink=\"false\">urn:uuid:{}</guid>", b.id)?;
+        writeln!(out, "      <pubDate>{}</pubDate>", added_at(b).rfc2822())?;
+        if let Some(desc) = &b.desc {
+            writeln!(out, "      <description>{}</description>", escape_html(desc))?;
+        }
+        for tag in &b.tags {
+            writeln!(out, "      <category>{}</category>", escape_html(tag))?;
+        }
+        writeln!(out, "    </item>")?;
+    }
+    writeln!(out, "  </channel>")?;
+    writeln!(out, "</rss>")?;
+    out.flush()?;
+    Ok(())
+}
+
+#[test]
+fn entries_newest_first_with_stable_ids() -> Result<()> {
+    let bookmarks = vec![
+        Bookmark {
+            id: "01910f6a-5fd6-7a3c-9c2e-0a1b2c3d4e5f".into(),
+            url: "https://docs.rs/?a=1&b=2".into(),
+            name: Some("Docs".into()),
+            tags: vec!["rust".into()],
+            added_at: "2020-12-31 23:59:59".into(),
+            ..Default::default()
+        },
+        Bookmark {
+            id: "01910f6a-5fd6-7a3c-9c2e-0a1b2c3d4e60".into(),
+            url: "https://blog.rust-lang.org/".into(),
+            added_at: "2024-08-05 14:03:09".into(),
+            ..Default::default()
+        },
+    ];
+    let feed = Feed { title: "Rust", id: feed_id("tag rust"), link: None };
+
+    let mut out = vec![];
+    atom(&feed, &bookmarks, &mut out)?;
+    let atom = String::from_utf8(out)?;
+    assert!(atom.contains("<id>urn:bmark:feed:tag-rust</id>\n  <updated>2024-08-05T14:03:09Z</updated>"));
+    let blog = atom.find("urn:uuid:01910f6a-5fd6-7a3c-9c2e-0a1b2c3d4e60").unwrap();
+    let docs = atom.find("urn:uuid:01910f6a-5fd6-7a3c-9c2e-0a1b2c3d4e5f").unwrap();
+    assert!(blog < docs);
+    assert!(atom.contains("<link href=\"https://docs.rs/?a=1&amp;b=2\"/>"));
+
+    assert!(rss(&feed, &bookmarks, vec![]).is_err());
+    let feed = Feed { link: Some("https://example.com/rust"), ..feed };
+    let mut out = vec![];
+    rss(&feed, &bookmarks, &mut out)?;
+    let rss = String::from_utf8(out)?;
+    assert!(rss.contains("<channel>\n    <title>Rust</title>\n    <link>https://example.com/rust</link>\n"));
+    assert!(rss.contains("<guid isPermaLink=\"false\">urn:uuid:01910f6a-5fd6-7a3c-9c2e-0a1b2c3d4e5f</guid>"));
+    assert!(rss.contains("<pubDate>Thu, 31 Dec 2020 23:59:59 +0000</pubDate>"));
+
+    let tagged = |tags: &[&str]| ListOptions { tags: tags.iter().map(|t| t.to_string()).collect(), ..Default::default() };
+    assert_eq!(selection(&tagged(&["web", "rust"]), None), selection(&tagged(&["rust", "web"]), None));
+    assert_eq!(selection(&tagged(&["web", "rust"]), Some("async")), "Bookmarks tagged rust or web, matching async");
+    let picky = ListOptions { pinned: true, min_rating: Some(4), status: Some(crate::ReadStatus::Unread), ..tagged(&["rust"]) };
+    assert_eq!(selection(&picky, None), "Bookmarks tagged rust, unread, pinned, rated 4 or more");
+    assert_ne!(feed_id(&selection(&picky, None)), feed_id(&selection(&tagged(&["rust"]), None)));
+
+    Ok(())
+}
@@ -0,0 +1,370 @@
+use std::{io::Read, time::Duration};
+
+use anyhow::{bail, Context, Result};
+use encoding_rs::{Encoding, UTF_8};
+use regex::Regex;
+
+use crate::import::unescape_html;
+
+const USER_AGENT: &str = concat!("bmark/", env!("CARGO_PKG_VERSION"));
+
+/// Limits on a single request
+#[derive(Clone, Copy)]
+pub struct FetchOptions {
+    /// For connecting and for the whole response each
+    pub timeout: Duration,
+    /// Bytes of the body read at most, the rest is ignored
+    pub max_bytes: u64,
+}
+
+impl Default for FetchOptions {
+    fn default() -> Self {
+        FetchOptions {
+            timeout: Duration::from_secs(10),
+            max_bytes: 2 * 1024 * 1024,
+        }
+    }
+}
+
+/// A fetched page, after following redirects
+#[derive(Debug)]
+pub struct Page {
+    /// Where the redirects (if any) ended up
+    pub url: String,
+    pub status: u16,
+    pub content_type: String,
+    pub body: Vec<u8>,
+}
+
+impl Page {
+    /// The body decoded with the charset the server gave or, for html, the one a `<meta>`
+    /// declares. UTF-8 when neither says.
+    pub fn text(&self) -> String {
+        self.encoding().decode(&self.body).0.into_owned()
+    }
+
+    pub fn encoding(&self) -> &'static Encoding {
+        let declared = charset_param(&self.content_type).or_else(|| self.is_html().then(|| meta_charset(&self.body)).flatten());
+        declared.and_then(|label| Encoding::for_label(label.as_bytes())).unwrap_or(UTF_8)
+    }
+
+    /// Whether the page is html, as the server says or, when it only says `text/plain` (which
+    /// is also what a missing content type reads as), as the body starts
+    pub fn is_html(&self) -> bool {
+        let mime = self.content_type.split(';').next().unwrap_or_default().trim().to_lowercase();
+        match mime.as_str() {
+            "text/html" | "application/xhtml+xml" => true,
+            "text/plain" => {
+                let start = String::from_utf8_lossy(&self.body[..self.body.len().min(256)]).trim_start().to_lowercase();
+                start.starts_with("<!doctype html") || start.starts_with("<html")
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The `charset` parameter of a content type, like `utf-8` in `text/html; charset=utf-8`
+fn charset_param(content_type: &str) -> Option<String> {
+    content_type.split(';').skip(1).find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        name.trim().eq_ignore_ascii_case("charset").then(|| value.trim().trim_matches(['"', '\'']).to_owned())
+    })
+}
+
+/// The charset declared by a `<meta charset>` or `<meta http-equiv="Content-Type">`, which
+/// has to come in the first 1024 bytes of a page
+fn meta_charset(body: &[u8]) -> Option<String> {
+    let head = String::from_utf8_lossy(&body[..body.len().min(1024)]).into_owned();
+    let re = Regex::new(r#"(?i)<meta\b[^>]*?charset\s*=\s*["']?\s*(?P<charset>[a-z0-9_.:-]+)"#).expect("Failed to create regex");
+    re.captures(&head).map(|cap| cap["charset"].to_owned())
+}
+
+pub(crate) fn agent_builder(options: &FetchOptions) -> ureq::AgentBuilder {
+    ureq::AgentBuilder::new()
+        .timeout_connect(options.timeout)
+        .timeout(options.timeout)
+        .user_agent(USER_AGENT)
+}
+
+/// GET `url`, failing on error statuses
+pub fn get(url: &str, options: &FetchOptions) -> Result<Page> {
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        bail!("Only http and https URLs can be fetched, not {}", url);
+    }
+    let response = match agent_builder(options).build().get(url).call() {
+        Ok(response) => response,
+        Err(ureq::Error::Status(status, _)) => bail!("{} answered with status {}", url, status),
+        Err(e) => return Err(e).context("Couldn't fetch the page"),
+    };
+    let mut page = Page {
+        url: response.get_url().to_owned(),
+        status: response.status(),
+        content_type: response.content_type().to_owned(),
+        body: vec![],
+    };
+    response
+        .into_reader()
+        .take(options.max_bytes)
+        .read_to_end(&mut page.body)
+        .with_context(|| format!("Couldn't read the page at {}", url))?;
+    Ok(page)
+}
+
+/// `href` as found on the page at `base`, made absolute
+pub fn resolve_url(base: &str, href: &str) -> String {
+    let href = href.trim();
+    if href.contains("://") {
+        return href.to_owned();
+    }
+    let scheme_end = base.find("://").map(|i| i + 3).unwrap_or(0);
+    if let Some(rest) = href.strip_prefix("//") {
+        return format!("{}{}", &base[..scheme_end], rest);
+    }
+    let origin_end = base[scheme_end..].find('/').map(|i| i + scheme_end).unwrap_or(base.len());
+    if href.starts_with('/') {
+        return format!("{}{}", &base[..origin_end], href);
+    }
+    let path = base.split(['?', '#']).next().unwrap_or(base);
+    let dir_end = path.rfind('/').filter(|i| *i >= origin_end).map(|i| i + 1);
+    match dir_end {
+        Some(end) => format!("{}{}", &path[..end], href),
+        None => format!("{}/{}", &base[..origin_end], href),
+    }
+}
+
+/// The host (and port) part of `url`
+pub fn host(url: &str) -> &str {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    rest.split(['/', '?', '#']).next().unwrap_or(rest)
+}
+
+/// What a page says about itself
+#[derive(Debug, Default, PartialEq)]
+pub struct Metadata {
+    pub title: Option<String>,
+    pub og_title: Option<String>,
+    /// OpenGraph description, or the plain meta description when there is none
+    pub description: Option<String>,
+    pub canonical_url: Option<String>,
+    pub lang: Option<String>,
+}
+
+impl Metadata {
+    /// Name to give the bookmark, OpenGraph titles tend to leave out the site name
+    pub fn name(&self) -> Option<&str> {
+        self.og_title.as_deref().or(self.title.as_deref())
+    }
+}
+
+/// Attributes of a tag, names lowercased
+pub(crate) fn attributes(tag: &str) -> Vec<(String, String)> {
+    let attr_re = Regex::new(r#"(?P<name>[a-zA-Z_:-]+)\s*=\s*(?:"(?P<dq>[^"]*)"|'(?P<sq>[^']*)'|(?P<bare>[^\s"'>]+))"#)
+        .expect("Failed to create regex");
+    attr_re
+        .captures_iter(tag)
+        .map(|cap| {
+            let value = cap.name("dq").or(cap.name("sq")).or(cap.name("bare")).map(|v| v.as_str()).unwrap_or_default();
+            (cap["name"].to_lowercase(), unescape_html(value))
+        })
+        .collect()
+}
+
+/// Read the metadata out of the html of the page at `url`
+pub fn parse_metadata(url: &str, html: &str) -> Metadata {
+    let clean = |text: &str| Some(unescape_html(&text.split_whitespace().collect::<Vec<_>>().join(" "))).filter(|t| !t.is_empty());
+    let title_re = Regex::new(r"(?is)<title[^>]*>(.*?)</title>").expect("Failed to create regex");
+    let tag_re = Regex::new(r"(?is)<(?P<name>html|meta|link)\b[^>]*>").expect("Failed to create regex");
+
+    let mut metadata = Metadata {
+        title: title_re.captures(html).and_then(|cap| clean(&cap[1])),
+        ..Default::default()
+    };
+    let mut meta_description = None;
+    for cap in tag_re.captures_iter(html) {
+        let attrs = attributes(&cap[0]);
+        let attr = |name: &str| attrs.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str());
+        match cap["name"].to_lowercase().as_str() {
+            "html" => metadata.lang = metadata.lang.take().or(attr("lang").and_then(clean)),
+            "link" => {
+                let canonical = attr("rel").is_some_and(|rel| rel.split_whitespace().any(|r| r.eq_ignore_ascii_case("canonical")));
+                if canonical && metadata.canonical_url.is_none() {
+                    metadata.canonical_url = attr("href").filter(|h| !h.trim().is_empty()).map(|h| resolve_url(url, h));
+                }
+            }
+            _ => {
+                let Some(content) = attr("content").and_then(clean) else { continue };
+                match attr("property").or(attr("name")).map(str::to_lowercase).as_deref() {
+                    Some("og:title") => metadata.og_title = metadata.og_title.take().or(Some(content)),
+                    Some("og:description") => metadata.description = metadata.description.take().or(Some(content)),
+                    Some("description") => meta_description = meta_description.or(Some(content)),
+                    _ => {}
+                }
+            }
+        }
+    }
+    metadata.description = metadata.description.or(meta_description);
+    metadata
+}
+
+/// Elements which hold no part of what a page is about
+const BOILERPLATE: [&str; 12] =
+    ["head", "script", "style", "noscript", "template", "svg", "iframe", "nav", "header", "footer", "aside", "form"];
+
+/// The text a reader would come for, without the markup, scripts, menus and footers around it.
+/// Paragraphs and other blocks end up on lines of their own.
+pub fn readable_text(html: &str) -> String {
+    let comment_re = Regex::new(r"(?s)<!--.*?-->").expect("Failed to create regex");
+    let mut html = comment_re.replace_all(html, " ").into_owned();
+    for element in BOILERPLATE {
+        let re = Regex::new(&format!(r"(?is)<{0}\b[^>]*>.*?</{0}\s*>", element)).expect("Failed to create regex");
+        html = re.replace_all(&html, " ").into_owned();
+    }
+    // when the page marks out its content, everything else is boilerplate too
+    for element in ["article", "main"] {
+        let re = Regex::new(&format!(r"(?is)<{0}\b[^>]*>(.*?)</{0}\s*>", element)).expect("Failed to create regex");
+        let parts = re.captures_iter(&html).map(|cap| cap[1].to_owned()).collect::<Vec<_>>();
+        if !parts.is_empty() {
+            html = parts.join("\n");
+            break;
+        }
+    }
+
+    let block_re = Regex::new(r"(?i)</?(p|div|br|li|h[1-6]|tr|blockquote|pre|section|dt|dd)\b[^>]*>").expect("Failed to create regex");
+    let tag_re = Regex::new(r"(?s)<[^>]*>").expect("Failed to create regex");
+    let numeric_re = Regex::new(r"&#(x[0-9a-fA-F]+|[0-9]+);").expect("Failed to create regex");
+    let html = block_re.replace_all(&html, "\n");
+    let text = tag_re.replace_all(&html, " ").replace("&nbsp;", " ");
+    let text = numeric_re.replace_all(&text, |cap: &regex::Captures| {
+        let code = match cap[1].strip_prefix('x') {
+            Some(hex) => u32::from_str_radix(hex, 16).ok(),
+            None => cap[1].parse().ok(),
+        };
+        code.and_then(char::from_u32).map(String::from).unwrap_or_default()
+    });
+    unescape_html(&text)
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Fetch the page at `url`, returning its metadata and readable text
+pub fn metadata(url: &str, options: &FetchOptions) -> Result<(Metadata, String)> {
+    let page = get(url, options)?;
+    if !page.content_type.contains("html") {
+        bail!("{} is {}, not a web page", url, page.content_type);
+    }
+    let html = page.text();
+    Ok((parse_metadata(&page.url, &html), readable_text(&html)))
+}
+
+/// Serve the response `respond` gives for each request path on a local port, until the test
+/// ends. Returns the base URL, like `http://127.0.0.1:4321`.
+#[cfg(test)]
+pub(crate) fn serve(respond: impl Fn(&str) -> String + Send + Sync + 'static) -> String {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        sync::Arc,
+        thread,
+    };
+
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Couldn't bind a local port");
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    let respond = Arc::new(respond);
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let respond = Arc::clone(&respond);
+            thread::spawn(move || {
+                let mut reader = BufReader::new(&stream);
+                let mut request_line = String::new();
+                _ = reader.read_line(&mut request_line);
+                let mut line = String::new();
+                while reader.read_line(&mut line).is_ok_and(|n| n > 2) {
+                    line.clear();
+                }
+                let path = request_line.split_whitespace().nth(1).unwrap_or("/").to_owned();
+                _ = (&stream).write_all(respond(&path).as_bytes());
+            });
+        }
+    });
+    base
+}
+
+/// A complete http response with the given status line, headers and body
+#[cfg(test)]
+pub(crate) fn response(status: &str, headers: &[(&str, &str)], body: &str) -> String {
+    let mut response = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n", status, body.len());
+    for (name, value) in headers {
+        response.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    format!("{}\r\n{}", response, body)
+}
+
+#[test]
+fn fetches_metadata_from_a_local_server() -> Result<()> {
+    let base = serve(|path| match path {
+        "/post" => response(
+            "200 OK",
+            &[("Content-Type", "text/html; charset=utf-8")],
+            "<!DOCTYPE html><html lang='en-GB'><head><title>\n  A post &amp; more | Blog\n</title>\
+             <meta property=\"og:title\" content=\"A post &amp; more\">\
+             <meta name=\"description\" content=\"Plain description\">\
+             <link rel=\"canonical\" href=\"/posts/a-post\"></head><body></body></html>",
+        ),
+        "/old" => response("301 Moved Permanently", &[("Location", "/post")], ""),
+        "/big" => response("200 OK", &[("Content-Type", "text/html")], &format!("<title>Big</title>{}", "x".repeat(4096))),
+        "/slow" => {
+            std::thread::sleep(Duration::from_secs(2));
+            response("200 OK", &[("Content-Type", "text/html")], "<title>Slow</title>")
+        }
+        "/image" => response("200 OK", &[("Content-Type", "image/png")], "png"),
+        _ => response("404 Not Found", &[], ""),
+    });
+    let options = FetchOptions { timeout: Duration::from_millis(500), max_bytes: 1024 };
+
+    let (metadata, text) = metadata(&format!("{}/old", base), &options)?;
+    assert_eq!(text, "");
+    assert_eq!(metadata.title.as_deref(), Some("A post & more | Blog"));
+    assert_eq!(metadata.name(), Some("A post & more"));
+    assert_eq!(metadata.description.as_deref(), Some("Plain description"));
+    assert_eq!(metadata.canonical_url, Some(format!("{}/posts/a-post", base)));
+    assert_eq!(metadata.lang.as_deref(), Some("en-GB"));
+
+    let big = get(&format!("{}/big", base), &options)?;
+    assert_eq!(big.body.len(), 1024);
+    assert!(get(&format!("{}/slow", base), &options).is_err());
+    assert!(get(&format!("{}/missing", base), &options).is_err());
+    assert!(self::metadata(&format!("{}/image", base), &options).is_err());
+    assert_eq!(resolve_url("https://example.com/a/b?c=/d", "e.png"), "https://example.com/a/e.png");
+    assert_eq!(resolve_url("https://example.com", "//cdn.example.com/e.png"), "https://cdn.example.com/e.png");
+
+    Ok(())
+}
+
+#[test]
+fn decodes_with_the_declared_charset() {
+    let page = |content_type: &str, body: &[u8]| Page {
+        url: String::from("https://example.com/"),
+        status: 200,
+        content_type: content_type.to_owned(),
+        body: body.to_vec(),
+    };
+    assert_eq!(page("text/html; charset=ISO-8859-1", b"caf\xe9").text(), "café");
+    assert!(page("text/html", b"<meta http-equiv=\"Content-Type\" content=\"text/html; charset=windows-1252\">\x93q\x94").text().ends_with(">“q”"));
+    assert_eq!(page("text/html", b"<meta charset='koi8-r'>\xf0\xd2\xc9\xd7\xc5\xd4").text(), "<meta charset='koi8-r'>Привет");
+    assert_eq!(page("text/html", "café".as_bytes()).text(), "café");
+    assert!(page("text/plain", b"  <!DOCTYPE html><p>").is_html());
+    assert!(!page("application/pdf", b"%PDF-1.4").is_html());
+}
+
+#[test]
+fn readable_text_skips_boilerplate() {
+    let html = "<html><head><title>Post</title><style>p { color: red }</style></head><body>\
+                <nav><a href=\"/\">Home</a></nav><header>Site</header>\
+                <main><h1>A   post</h1><!-- draft --><p>First&nbsp;paragraph &amp; <em>more</em>.</p>\
+                <script>track()</script><p>Caf&#233; &#x2014; done</p></main>\
+                <footer>Copyright</footer></body></html>";
+    assert_eq!(readable_text(html), "A post\nFirst paragraph & more .\nCafé — done");
+}
@@ -0,0 +1,86 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use rusqlite::{Connection, OpenFlags};
+
+use super::Batch;
+use crate::NewBookmark;
+
+/// Read the `bookmarks` table of a buku database
+pub fn read(path: &Path) -> Result<Batch> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("Couldn't open {}", path.display()))?;
+    read_bookmarks(&conn)
+}
+
+fn read_bookmarks(conn: &Connection) -> Result<Batch> {
+    let mut stmt = conn.prepare("SELECT id, URL, metadata, tags, desc, flags FROM bookmarks ORDER BY id")?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, Option<String>>(1)?,
+            row.get::<_, Option<String>>(2)?,
+            row.get::<_, Option<String>>(3)?,
+            row.get::<_, Option<String>>(4)?,
+            row.get::<_, Option<i64>>(5)?,
+        ))
+    })?;
+
+    let mut batch = Batch::default();
+    for row in rows {
+        let (id, url, title, tags, desc, flags) = row?;
+        let Some(url) = url.filter(|u| !u.trim().is_empty()) else {
+            batch.skipped.push(format!("buku bookmark #{}: no URL", id));
+            continue;
+        };
+        // the only flag buku has marks the title as immutable, bmark never rewrites titles anyway
+        if let Some(flags) = flags.filter(|f| *f != 0) {
+            batch.ignored.push(format!("buku bookmark #{}: flags {}, bmark has no such setting", id, flags));
+        }
+        batch.bookmarks.push(NewBookmark {
+            url,
+            name: title.filter(|t| !t.is_empty()),
+            tags: split_tags(tags.as_deref().unwrap_or_default()),
+            desc: desc.filter(|d| !d.is_empty()),
+            ..Default::default()
+        });
+    }
+    Ok(batch)
+}
+
+/// buku keeps tags as one string delimited (and wrapped) by commas, like `,rust,docs,`
+fn split_tags(tags: &str) -> Vec<String> {
+    let mut split: Vec<String> = vec![];
+    for tag in tags.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+        if !split.iter().any(|t| t == tag) {
+            split.push(tag.to_owned());
+        }
+    }
+    split
+}
+
+#[test]
+fn maps_buku_rows() -> Result<()> {
+    let conn = Connection::open_in_memory()?;
+    conn.execute_batch(
+        "CREATE TABLE bookmarks (id integer PRIMARY KEY, URL text NOT NULL UNIQUE, metadata text default '', tags text default ',', desc text default '', flags integer default 0);
+         INSERT INTO bookmarks (URL, metadata, tags, desc) VALUES
+             ('https://github.com/jarun/buku', 'buku', ',cli,bookmarks, cli,', 'Bookmark manager');
+         INSERT INTO bookmarks (URL, metadata, tags, desc, flags) VALUES ('https://example.org', 'fixed', ',', '', 1);
+         INSERT INTO bookmarks (URL, metadata, tags, desc) VALUES
+             ('https://example.com', '', ',', ''),
+             ('  ', 'blank', ',', '');",
+    )?;
+
+    let batch = read_bookmarks(&conn)?;
+    assert_eq!(batch.bookmarks.len(), 3);
+    assert_eq!(batch.skipped, vec!["buku bookmark #4: no URL"]);
+    assert_eq!(batch.ignored, vec!["buku bookmark #2: flags 1, bmark has no such setting"]);
+    let buku = &batch.bookmarks[0];
+    assert_eq!(buku.name.as_deref(), Some("buku"));
+    assert_eq!(buku.tags, vec!["cli", "bookmarks"]);
+    assert_eq!(buku.desc.as_deref(), Some("Bookmark manager"));
+    assert!(batch.bookmarks[2].tags.is_empty());
+
+    Ok(())
+}
@@ -0,0 +1,104 @@
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+use super::Batch;
+use crate::{date, NewBookmark};
+
+/// Roots of the bookmark tree which get imported, in this order
+const ROOTS: [&str; 3] = ["bookmark_bar", "other", "synced"];
+
+/// Read the `Bookmarks` file of a Chromium-family browser profile, `path` being the profile
+/// directory or the file itself
+pub fn read(path: &Path) -> Result<Batch> {
+    let file = if path.is_dir() {
+        path.join("Bookmarks")
+    } else {
+        path.to_path_buf()
+    };
+    let content = fs::read_to_string(&file).with_context(|| format!("Couldn't read {}", file.display()))?;
+    parse(&content)
+}
+
+fn parse(content: &str) -> Result<Batch> {
+    let tree: Value = serde_json::from_str(content).context("Bookmarks file isn't valid json")?;
+    let roots = tree.get("roots").context("Bookmarks file has no roots")?;
+
+    let mut batch = Batch::default();
+    for root in ROOTS {
+        if let Some(node) = roots.get(root) {
+            walk(node, &mut vec![], &mut batch);
+        }
+    }
+    Ok(batch)
+}
+
+/// Collect the urls under `node`, `folders` being the names of the folders leading to it
+fn walk<'a>(node: &'a Value, folders: &mut Vec<&'a str>, batch: &mut Batch) {
+    let name = node.get("name").and_then(Value::as_str).unwrap_or_default();
+    match node.get("type").and_then(Value::as_str) {
+        Some("folder") => {
+            folders.push(name);
+            for child in node.get("children").and_then(Value::as_array).into_iter().flatten() {
+                walk(child, folders, batch);
+            }
+            folders.pop();
+        }
+        Some("url") => {
+            let Some(url) = node.get("url").and_then(Value::as_str) else {
+                batch.skipped.push(format!("{}: entry without a URL", name));
+                return;
+            };
+            // chromium writes the webkit timestamps as strings
+            let added_at = node
+                .get("date_added")
+                .and_then(Value::as_str)
+                .and_then(|d| d.parse::<u64>().ok())
+                .filter(|d| *d > 0)
+                .map(|d| format!("{}", date::get_datetime_for_webkit_time(d)));
+            batch.bookmarks.push(NewBookmark {
+                url: url.to_owned(),
+                name: Some(name.to_owned()).filter(|n| !n.is_empty()),
+                category: Some(folders.join("/")).filter(|c| !c.is_empty()),
+                added_at,
+                ..Default::default()
+            });
+        }
+        other => batch.skipped.push(format!("{}: unknown node type {:?}", name, other)),
+    }
+}
+
+#[test]
+fn walks_roots_and_folders() -> Result<()> {
+    let content = r#"{
+        "checksum": "0",
+        "roots": {
+            "bookmark_bar": {
+                "type": "folder", "name": "Bookmarks bar", "children": [
+                    { "type": "folder", "name": "Rust", "children": [
+                        { "type": "url", "name": "Docs", "url": "https://docs.rs/", "date_added": "13253932799000000" }
+                    ]}
+                ]
+            },
+            "other": {
+                "type": "folder", "name": "Other bookmarks", "children": [
+                    { "type": "url", "name": "", "url": "https://example.com/", "date_added": "0" }
+                ]
+            },
+            "synced": { "type": "folder", "name": "Mobile bookmarks", "children": [] }
+        },
+        "version": 1
+    }"#;
+
+    let batch = parse(content)?;
+    assert_eq!(batch.bookmarks.len(), 2);
+    let docs = &batch.bookmarks[0];
+    assert_eq!(docs.category.as_deref(), Some("Bookmarks bar/Rust"));
+    assert_eq!(docs.added_at.as_deref(), Some("2020-12-31 23:59:59"));
+    let other = &batch.bookmarks[1];
+    assert_eq!(other.name, None);
+    assert_eq!(other.added_at, None);
+
+    Ok(())
+}
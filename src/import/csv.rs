@@ -0,0 +1,170 @@
+use std::{fs, path::Path};
+
+use anyhow::{bail, Context, Result};
+
+use super::{iso_timestamp, timestamp, Batch};
+use crate::{date, NewBookmark};
+
+/// Bookmark fields a column can be mapped to, with the headers recognised for each
+const FIELDS: [(&str, &[&str]); 6] = [
+    ("url", &["url", "link", "href", "uri", "address"]),
+    ("name", &["name", "title"]),
+    ("tags", &["tags", "tag", "labels", "label", "keywords"]),
+    ("desc", &["desc", "description", "note", "notes", "comment", "extended"]),
+    ("category", &["category", "folder", "collection", "group"]),
+    ("added_at", &["added_at", "added", "date", "created", "created_at", "time_added"]),
+];
+
+pub struct CsvOptions {
+    /// `field=Header` pairs overriding the detected mapping
+    pub map: Vec<String>,
+    /// What the tags in a cell are separated with
+    pub tag_separator: String,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            map: vec![],
+            tag_separator: String::from(","),
+        }
+    }
+}
+
+/// Which column each field is read from, as (field, column index, header)
+pub type Mapping = Vec<(&'static str, usize, String)>;
+
+/// Read a csv file with a header row, returning the column mapping used along with the rows
+pub fn read(path: &Path, options: &CsvOptions) -> Result<(Mapping, Batch)> {
+    let content = fs::read_to_string(path).with_context(|| format!("Couldn't read {}", path.display()))?;
+    parse(&content, options)
+}
+
+fn mapping(headers: &csv::StringRecord, options: &CsvOptions) -> Result<Mapping> {
+    let find = |header: &str| headers.iter().position(|h| h.trim().eq_ignore_ascii_case(header.trim()));
+
+    let mut mapping: Mapping = vec![];
+    for pair in options.map.iter().flat_map(|m| m.split(',')).filter(|p| !p.trim().is_empty()) {
+        let Some((field, header)) = pair.split_once('=') else {
+            bail!("Invalid mapping {:?}, expected field=Header", pair);
+        };
+        let Some((field, _)) = FIELDS.iter().find(|(f, _)| *f == field.trim()) else {
+            let known = FIELDS.iter().map(|(f, _)| *f).collect::<Vec<_>>();
+            bail!("Unknown field {:?} in mapping, known fields are {}", field, known.join(", "));
+        };
+        let column = find(header).with_context(|| format!("No column named {:?}", header))?;
+        mapping.retain(|(f, _, _)| f != field);
+        mapping.push((field, column, headers[column].to_owned()));
+    }
+
+    for (field, synonyms) in FIELDS {
+        if mapping.iter().any(|(f, _, _)| *f == field) {
+            continue;
+        }
+        if let Some(column) = synonyms.iter().find_map(|s| find(s)) {
+            mapping.push((field, column, headers[column].to_owned()));
+        }
+    }
+    if !mapping.iter().any(|(f, _, _)| *f == "url") {
+        bail!("Couldn't tell which column holds the URL, map it with --map url=<Header>");
+    }
+
+    Ok(mapping)
+}
+
+/// `added_at` for the timestamp formats spreadsheets tend to have
+fn parse_timestamp(value: &str) -> Option<String> {
+    if let Ok(epoch) = value.parse::<u64>() {
+        return Some(timestamp(epoch));
+    }
+    date::parse_date(value).ok().map(|d| format!("{}", d)).or_else(|| iso_timestamp(value))
+}
+
+fn parse(content: &str, options: &CsvOptions) -> Result<(Mapping, Batch)> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(content.as_bytes());
+    let mapping = mapping(reader.headers()?, options)?;
+
+    let mut batch = Batch::default();
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                let line = e.position().map(|p| p.line()).unwrap_or_default();
+                batch.skipped.push(format!("csv line {}: {}", line, e));
+                continue;
+            }
+        };
+        let line = record.position().map(|p| p.line()).unwrap_or_default();
+        let mut new = NewBookmark::default();
+        let mut invalid = None;
+        for (field, column, _) in &mapping {
+            let value = record.get(*column).unwrap_or_default().trim();
+            if value.is_empty() {
+                continue;
+            }
+            match *field {
+                "url" => new.url = value.to_owned(),
+                "name" => new.name = Some(value.to_owned()),
+                "tags" => {
+                    new.tags = value
+                        .split(options.tag_separator.as_str())
+                        .map(str::trim)
+                        .filter(|t| !t.is_empty())
+                        .map(String::from)
+                        .collect()
+                }
+                "desc" => new.desc = Some(value.to_owned()),
+                "category" => new.category = Some(value.to_owned()),
+                _ => match parse_timestamp(value) {
+                    Some(added_at) => new.added_at = Some(added_at),
+                    None => invalid = Some(format!("unrecognised date {:?}", value)),
+                },
+            }
+        }
+        if new.url.is_empty() {
+            invalid = Some(String::from("no URL"));
+        } else if !new.url.contains(':') {
+            invalid = Some(format!("{:?} isn't a URL", new.url));
+        }
+
+        match invalid {
+            Some(reason) => batch.skipped.push(format!("csv line {}: {}", line, reason)),
+            None => batch.bookmarks.push(new),
+        }
+    }
+    Ok((mapping, batch))
+}
+
+#[test]
+fn detects_and_overrides_columns() -> Result<()> {
+    let content = "Title,Link,Labels,Added\n\
+                   Docs,https://docs.rs/,rust;docs,2020-12-31 23:59:59\n\
+                   No link,,,\n\
+                   Bad date,https://example.com/,,yesterday\n\
+                   Relative,/just/a/path,,\n";
+
+    let options = CsvOptions {
+        map: vec![String::from("tags=labels")],
+        tag_separator: String::from(";"),
+    };
+    let (mapping, batch) = parse(content, &options)?;
+    let fields = mapping.iter().map(|(f, _, h)| format!("{}={}", f, h)).collect::<Vec<_>>();
+    assert_eq!(fields, ["tags=Labels", "url=Link", "name=Title", "added_at=Added"]);
+
+    assert_eq!(batch.bookmarks.len(), 1);
+    assert_eq!(batch.bookmarks[0].tags, vec!["rust", "docs"]);
+    assert_eq!(batch.bookmarks[0].added_at.as_deref(), Some("2020-12-31 23:59:59"));
+    assert_eq!(
+        batch.skipped,
+        [
+            "csv line 3: no URL",
+            "csv line 4: unrecognised date \"yesterday\"",
+            "csv line 5: \"/just/a/path\" isn't a URL"
+        ]
+    );
+
+    assert!(parse("Title,Notes\nDocs,x\n", &CsvOptions::default()).is_err());
+    assert!(parse(content, &CsvOptions { map: vec![String::from("colour=Title")], ..Default::default() }).is_err());
+
+    Ok(())
+}
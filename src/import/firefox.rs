@@ -0,0 +1,145 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::{Context, Result};
+use rusqlite::{Connection, OptionalExtension};
+
+use super::{scratch_dir, timestamp, Batch};
+use crate::NewBookmark;
+
+const ROOT_GUID: &str = "root________";
+const TAGS_GUID: &str = "tags________";
+
+/// Read the bookmarks of a Firefox profile, `path` being the profile directory or its
+/// `places.sqlite`. Firefox keeps the db locked while running, so a copy of it is read.
+pub fn read(path: &Path) -> Result<Batch> {
+    let places = if path.is_dir() {
+        path.join("places.sqlite")
+    } else {
+        path.to_path_buf()
+    };
+    let scratch = scratch_dir("firefox")?;
+    let copy = scratch.join("places.sqlite");
+    fs::copy(&places, &copy).with_context(|| format!("Couldn't copy {}", places.display()))?;
+    // recent changes may still sit in the write-ahead log
+    let wal = places.with_file_name("places.sqlite-wal");
+    if wal.exists() {
+        fs::copy(&wal, scratch.join("places.sqlite-wal"))?;
+    }
+
+    let batch = read_places(&Connection::open(&copy)?);
+    _ = fs::remove_dir_all(&scratch);
+    batch
+}
+
+struct Folder {
+    parent: i64,
+    title: String,
+    guid: String,
+}
+
+fn read_places(conn: &Connection) -> Result<Batch> {
+    let folders = {
+        let mut stmt = conn.prepare("SELECT id, parent, coalesce(title, ''), guid FROM moz_bookmarks WHERE type=2")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, Folder { parent: row.get(1)?, title: row.get(2)?, guid: row.get(3)? }))
+        })?;
+        rows.collect::<Result<HashMap<_, _>, _>>()?
+    };
+    let tags_root = conn
+        .query_row("SELECT id FROM moz_bookmarks WHERE guid=?1", [TAGS_GUID], |row| row.get::<_, i64>(0))
+        .optional()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT b.fk, b.parent, b.title, b.dateAdded, p.url, p.title \
+         FROM moz_bookmarks b JOIN moz_places p ON p.id=b.fk WHERE b.type=1 ORDER BY b.dateAdded, b.id",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, i64>(1)?,
+            row.get::<_, Option<String>>(2)?,
+            row.get::<_, Option<i64>>(3)?,
+            row.get::<_, String>(4)?,
+            row.get::<_, Option<String>>(5)?,
+        ))
+    })?;
+
+    let mut batch = Batch::default();
+    // tags are bookmarks of the same place filed under a folder (named after the tag) in the tags root
+    let mut tags: HashMap<i64, Vec<String>> = HashMap::new();
+    let mut places: Vec<i64> = vec![];
+    for row in rows {
+        let (place, parent, title, date_added, url, place_title) = row?;
+        if let Some(tag) = folders.get(&parent).filter(|f| Some(f.parent) == tags_root) {
+            tags.entry(place).or_default().push(tag.title.clone());
+            continue;
+        }
+        if url.starts_with("place:") {
+            batch.skipped.push(format!("saved query {}: not a bookmark", url));
+            continue;
+        }
+
+        batch.bookmarks.push(NewBookmark {
+            url,
+            name: title.or(place_title).filter(|t| !t.is_empty()),
+            category: folder_path(&folders, parent),
+            // dateAdded is in microseconds, and missing when it isn't a time after 1970
+            added_at: date_added.filter(|d| *d > 0).map(|d| timestamp((d / 1_000_000) as u64)),
+            ..Default::default()
+        });
+        places.push(place);
+    }
+
+    for (bookmark, place) in batch.bookmarks.iter_mut().zip(places) {
+        bookmark.tags = tags.get(&place).cloned().unwrap_or_default();
+    }
+    Ok(batch)
+}
+
+/// Titles of the folders from the root down to `folder`, like `toolbar/Rust`
+fn folder_path(folders: &HashMap<i64, Folder>, folder: i64) -> Option<String> {
+    let mut titles = vec![];
+    let mut current = folders.get(&folder);
+    while let Some(f) = current.filter(|f| f.guid != ROOT_GUID) {
+        titles.push(f.title.as_str());
+        current = folders.get(&f.parent);
+    }
+    titles.reverse();
+    Some(titles.join("/")).filter(|p| !p.is_empty())
+}
+
+#[test]
+fn reads_folders_tags_and_dates() -> Result<()> {
+    let conn = Connection::open_in_memory()?;
+    conn.execute_batch(
+        "CREATE TABLE moz_places (id INTEGER PRIMARY KEY, url TEXT, title TEXT);
+         CREATE TABLE moz_bookmarks (id INTEGER PRIMARY KEY, type INTEGER, fk INTEGER, parent INTEGER, title TEXT, dateAdded INTEGER, guid TEXT);
+         INSERT INTO moz_places VALUES (1, 'https://rust-lang.org/', 'Rust Programming Language'), (2, 'place:sort=8', NULL),
+             (3, 'https://example.com/', 'Example');
+         INSERT INTO moz_bookmarks VALUES
+             (1, 2, NULL, 0, '', 0, 'root________'),
+             (2, 2, NULL, 1, 'menu', 0, 'menu________'),
+             (3, 2, NULL, 1, 'toolbar', 0, 'toolbar_____'),
+             (4, 2, NULL, 1, 'tags', 0, 'tags________'),
+             (5, 2, NULL, 3, 'Rust', 0, 'folder000001'),
+             (6, 2, NULL, 4, 'lang', 0, 'folder000002'),
+             (7, 1, 1, 5, 'Rust', 1609459199000000, 'bmark0000001'),
+             (8, 1, 1, 6, NULL, 1609459199000000, 'bmark0000002'),
+             (9, 1, 2, 2, 'Recent Tags', 0, 'bmark0000003'),
+             (10, 1, 3, 2, NULL, -1609459199000000, 'bmark0000004');",
+    )?;
+
+    let batch = read_places(&conn)?;
+    assert_eq!(batch.bookmarks.len(), 2);
+    assert_eq!(batch.skipped.len(), 1);
+    let rust = batch.bookmarks.iter().find(|b| b.url == "https://rust-lang.org/").unwrap();
+    assert_eq!(rust.name.as_deref(), Some("Rust"));
+    assert_eq!(rust.category.as_deref(), Some("toolbar/Rust"));
+    assert_eq!(rust.tags, vec!["lang"]);
+    assert_eq!(rust.added_at.as_deref(), Some("2020-12-31 23:59:59"));
+    // a corrupt date counts as none rather than one billions of years away
+    let example = batch.bookmarks.iter().find(|b| b.url == "https://example.com/").unwrap();
+    assert_eq!((example.name.as_deref(), example.added_at.as_deref()), (Some("Example"), None));
+
+    Ok(())
+}
@@ -0,0 +1,140 @@
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result};
+
+use super::Batch;
+use crate::{
+    export::{Dump, Header},
+    Bookmark, NewBookmark,
+};
+
+/// Read a dump written by `bmark export --format json` (or `jsonl` when `lines` is set).
+/// Ids are kept unless `new_ids` asks for fresh ones.
+pub fn read(path: &Path, lines: bool, new_ids: bool) -> Result<Batch> {
+    let content = fs::read_to_string(path).with_context(|| format!("Couldn't read {}", path.display()))?;
+    let bookmarks = if lines { parse_lines(&content)? } else { parse(&content)? };
+
+    let mut batch = Batch::default();
+    for bookmark in bookmarks {
+        let mut new = NewBookmark::from(bookmark);
+        if new_ids {
+            new.id = None;
+        }
+        batch.bookmarks.push(new);
+    }
+    Ok(batch)
+}
+
+fn parse(content: &str) -> Result<Vec<Bookmark>> {
+    let dump: Dump = serde_json::from_str(content).context("Not a bmark json dump")?;
+    dump.header.check()?;
+    Ok(dump.bookmarks)
+}
+
+fn parse_lines(content: &str) -> Result<Vec<Bookmark>> {
+    let mut lines = content.lines().enumerate().filter(|(_, l)| !l.trim().is_empty());
+    let (_, header) = lines.next().context("Empty jsonl dump")?;
+    let header: Header = serde_json::from_str(header).context("First line of a jsonl dump should be its header")?;
+    header.check()?;
+
+    lines
+        .map(|(i, line)| serde_json::from_str(line).with_context(|| format!("Invalid bookmark on line {}", i + 1)))
+        .collect()
+}
+
+#[test]
+fn round_trips_through_both_formats() -> Result<()> {
+    use crate::{check::LinkStatus, rescue::Alternate, Note, PageMeta, ReadStatus, Reminder, Revision};
+
+    let bookmarks = vec![Bookmark {
+        id: "01910f6a-5fd6-7a3c-9c2e-0a1b2c3d4e5f".into(),
+        url: "https://docs.rs/".into(),
+        name: Some("Docs".into()),
+        tags: vec!["docs".into(), "rust".into()],
+        added_at: "2020-12-31 23:59:59".into(),
+        deleted_at: Some("2021-01-01 00:00:00".into()),
+        status: Some(ReadStatus::Unread),
+        unread_at: Some("2020-12-31 23:59:59".into()),
+        pinned: true,
+        rating: Some(4),
+        notes: vec![Note { seq: 1, body: "Read the *book* first".into(), created_at: "2021-01-01 00:00:00".into(), updated_at: None }],
+        alternates: vec![Alternate {
+            url: "https://web.archive.org/web/20201231000000/https://docs.rs/".into(),
+            source: "wayback".into(),
+            captured_at: Some("2020-12-31 00:00:00".into()),
+            added_at: "2021-01-01 00:00:00".into(),
+        }],
+        reminder: Some(Reminder { due_at: "2021-02-01 09:00:00".into(), created_at: "2021-01-01 00:00:00".into(), snoozed: 2, ..Default::default() }),
+        visits: vec!["2021-01-01 00:00:00".into(), "2021-01-02 08:30:00".into()],
+        page_meta: Some(PageMeta {
+            title: Some("Docs.rs".into()),
+            description: Some("Documentation for Rust crates".into()),
+            canonical_url: Some("https://docs.rs/".into()),
+            lang: Some("en".into()),
+            fetched_at: "2020-12-31 23:59:59".into(),
+        }),
+        revisions: vec![
+            Revision { rev: 1, url: "http://docs.rs/".into(), tags: vec!["rust".into()], created_at: "2020-12-31 23:59:59".into(), ..Default::default() },
+            Revision {
+                rev: 2,
+                url: "https://docs.rs/".into(),
+                name: Some("Docs".into()),
+                tags: vec!["docs".into(), "rust".into()],
+                created_at: "2021-01-01 00:00:00".into(),
+                ..Default::default()
+            },
+        ],
+        link_statuses: vec![
+            LinkStatus {
+                url: "https://docs.rs/".into(),
+                status: Some(200),
+                final_url: "https://docs.rs/".into(),
+                checked_at: "2021-01-02 00:00:00".into(),
+                ..Default::default()
+            },
+            LinkStatus {
+                url: "http://docs.rs/".into(),
+                status: Some(200),
+                final_url: "https://docs.rs/".into(),
+                permanent: true,
+                checked_at: "2021-01-01 00:00:00".into(),
+                ..Default::default()
+            },
+        ],
+        ..Default::default()
+    }];
+
+    let mut out = vec![];
+    crate::export::json(&bookmarks, &mut out)?;
+    let read = parse(&String::from_utf8(out)?)?;
+    assert_eq!(format!("{:?}", read), format!("{:?}", bookmarks));
+
+    let mut out = vec![];
+    crate::export::jsonl(&bookmarks, &mut out)?;
+    let read = parse_lines(&String::from_utf8(out)?)?;
+    assert_eq!(format!("{:?}", read), format!("{:?}", bookmarks));
+
+    assert!(parse_lines("{\"format\":\"bmark\",\"version\":99,\"exported_at\":\"\"}").is_err());
+
+    // and through a db, the way a dump is restored
+    let mut bmark = crate::BMark { conn: crate::get_db_connection(None)? };
+    bmark.setup()?;
+    bmark.import("dump", Batch { bookmarks: read.into_iter().map(NewBookmark::from).collect(), ..Default::default() })?;
+    let mut restored = bmark.all_bookmarks()?;
+    assert_eq!(restored[0].reminder.as_ref().map(|r| r.bmark_id.as_str()), Some(bookmarks[0].id.as_str()));
+    restored[0].reminder.as_mut().unwrap().bmark_id.clear();
+    assert!(restored[0].link_statuses.iter().all(|s| s.bmark_id == bookmarks[0].id));
+    restored[0].link_statuses.iter_mut().for_each(|s| s.bmark_id.clear());
+    assert_eq!(format!("{:?}", restored), format!("{:?}", bookmarks));
+    // restoring the dump is one operation, undone as a whole
+    bmark.undo(1)?;
+    assert!(bmark.all_bookmarks()?.is_empty());
+    let leftovers: i64 = bmark.conn.query_row(
+        "SELECT (SELECT count(*) FROM visit) + (SELECT count(*) FROM page_meta) + (SELECT count(*) FROM revision) + (SELECT count(*) FROM link_status)",
+        [],
+        |row| row.get(0),
+    )?;
+    assert_eq!(leftovers, 0);
+
+    Ok(())
+}
@@ -0,0 +1,69 @@
+use std::{env, fs, path::PathBuf};
+
+use anyhow::Result;
+
+use crate::{date, NewBookmark};
+
+pub mod buku;
+pub mod chrome;
+pub mod csv;
+pub mod firefox;
+pub mod json;
+pub mod pinboard;
+pub mod pocket;
+pub mod scan;
+
+/// What an importer read out of its source
+#[derive(Debug, Default)]
+pub struct Batch {
+    pub bookmarks: Vec<NewBookmark>,
+    /// Entries which couldn't be turned into a bookmark, along with why
+    pub skipped: Vec<String>,
+    /// Parts of entries which were imported without them, along with why
+    pub ignored: Vec<String>,
+}
+
+/// Outcome of `BMark::import`
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub added: usize,
+    /// URLs which were already bookmarked
+    pub duplicates: Vec<String>,
+    pub skipped: Vec<String>,
+    pub ignored: Vec<String>,
+}
+
+/// `added_at` for a unix timestamp in seconds
+fn timestamp(epoch: u64) -> String {
+    format!("{}", date::get_datetime_for_epochs(epoch))
+}
+
+/// `added_at` for an ISO 8601 UTC timestamp like `2020-12-31T23:59:59Z`
+fn iso_timestamp(iso: &str) -> Option<String> {
+    let datetime = iso.get(..19)?.replacen('T', " ", 1);
+    date::parse_date(&datetime).ok().map(|d| format!("{}", d))
+}
+
+/// Decode the few entities bookmark exports put in names and attributes
+pub(crate) fn unescape_html(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&amp;", "&")
+}
+
+/// A fresh directory under the system temp dir for copies of the files being imported
+fn scratch_dir(name: &str) -> Result<PathBuf> {
+    let dir = env::temp_dir().join(format!("bmark-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+#[test]
+fn test_iso_timestamp() {
+    assert_eq!(iso_timestamp("2020-12-31T23:59:59Z").as_deref(), Some("2020-12-31 23:59:59"));
+    assert_eq!(iso_timestamp("2020-12-31"), None);
+    assert_eq!(unescape_html("Tom &amp; Jerry &lt;3"), "Tom & Jerry <3");
+}
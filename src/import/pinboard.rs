@@ -0,0 +1,70 @@
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+use super::{iso_timestamp, Batch};
+use crate::{NewBookmark, ReadStatus};
+
+/// Read a Pinboard json export, an array of posts
+pub fn read(path: &Path) -> Result<Batch> {
+    let content = fs::read_to_string(path).with_context(|| format!("Couldn't read {}", path.display()))?;
+    parse(&content)
+}
+
+fn parse(content: &str) -> Result<Batch> {
+    let posts: Vec<Value> = serde_json::from_str(content).context("Pinboard export should be a json array")?;
+
+    let mut batch = Batch::default();
+    for (i, post) in posts.iter().enumerate() {
+        let field = |name: &str| {
+            post.get(name)
+                .and_then(Value::as_str)
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(String::from)
+        };
+        let Some(url) = field("href") else {
+            batch.skipped.push(format!("pinboard post #{}: no href", i + 1));
+            continue;
+        };
+
+        let tags = field("tags")
+            .map(|t| t.split_whitespace().map(String::from).collect::<Vec<_>>())
+            .unwrap_or_default();
+        let toread = field("toread").as_deref() == Some("yes");
+        batch.bookmarks.push(NewBookmark {
+            url,
+            name: field("description"),
+            desc: field("extended"),
+            tags,
+            added_at: field("time").and_then(|t| iso_timestamp(&t)),
+            status: toread.then_some(ReadStatus::Unread),
+            ..Default::default()
+        });
+    }
+    Ok(batch)
+}
+
+#[test]
+fn maps_pinboard_posts() -> Result<()> {
+    let content = r#"[
+        {"href":"https://docs.rs/","description":"Docs.rs","extended":"Crate docs","meta":"x","hash":"y","time":"2020-12-31T23:59:59Z","shared":"no","toread":"yes","tags":"rust docs"},
+        {"href":"https://example.com/","description":"","extended":"","time":"2021-01-01T00:00:00Z","shared":"yes","toread":"no","tags":""},
+        {"description":"no link"}
+    ]"#;
+
+    let batch = parse(content)?;
+    assert_eq!(batch.bookmarks.len(), 2);
+    assert_eq!(batch.skipped.len(), 1);
+    let docs = &batch.bookmarks[0];
+    assert_eq!(docs.name.as_deref(), Some("Docs.rs"));
+    assert_eq!(docs.desc.as_deref(), Some("Crate docs"));
+    assert_eq!(docs.tags, vec!["rust", "docs"]);
+    assert_eq!(docs.added_at.as_deref(), Some("2020-12-31 23:59:59"));
+    assert_eq!(docs.status, Some(ReadStatus::Unread));
+    assert!(batch.bookmarks[1].tags.is_empty());
+    assert_eq!(batch.bookmarks[1].status, None);
+
+    Ok(())
+}
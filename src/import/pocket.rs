@@ -0,0 +1,150 @@
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result};
+use regex::Regex;
+
+use super::{timestamp, unescape_html, Batch};
+use crate::{NewBookmark, ReadStatus};
+
+/// Read a Pocket export, either the older `ril_export.html` or the newer `part_*.csv`
+pub fn read(path: &Path) -> Result<Batch> {
+    let content = fs::read_to_string(path).with_context(|| format!("Couldn't read {}", path.display()))?;
+    if content.trim_start().starts_with('<') {
+        Ok(parse_html(&content))
+    } else {
+        parse_csv(&content)
+    }
+}
+
+fn bookmark(url: &str, title: &str, time_added: &str, tags: Vec<String>, unread: bool) -> NewBookmark {
+    NewBookmark {
+        url: url.to_owned(),
+        // pocket falls back to the url when it couldn't get a title
+        name: Some(title.trim().to_owned()).filter(|t| !t.is_empty() && t != url),
+        tags,
+        added_at: time_added.trim().parse::<u64>().ok().map(timestamp),
+        // everything saved to pocket is in its reading queue, the archive holds what was read
+        status: Some(if unread { ReadStatus::Unread } else { ReadStatus::Read }),
+        ..Default::default()
+    }
+}
+
+/// The html export lists the items as links under an `Unread` and a `Read Archive` heading
+fn parse_html(content: &str) -> Batch {
+    let re = Regex::new(r"(?is)<h1[^>]*>(?P<heading>.*?)</h1>|<a\s(?P<attrs>[^>]*)>(?P<title>.*?)</a>")
+        .expect("Failed to create regex");
+    let attr_re = Regex::new(r#"(?i)(?P<name>[a-z_]+)\s*=\s*"(?P<value>[^"]*)""#).expect("Failed to create regex");
+
+    let mut batch = Batch::default();
+    let mut unread = true;
+    for cap in re.captures_iter(content) {
+        if let Some(heading) = cap.name("heading") {
+            unread = heading.as_str().trim().eq_ignore_ascii_case("unread");
+            continue;
+        }
+        let attr = |name: &str| {
+            attr_re
+                .captures_iter(&cap["attrs"])
+                .find(|a| a["name"].eq_ignore_ascii_case(name))
+                .map(|a| unescape_html(&a["value"]))
+                .unwrap_or_default()
+        };
+        let url = attr("href");
+        if url.is_empty() {
+            batch.skipped.push(format!("pocket link {}: no href", &cap["title"]));
+            continue;
+        }
+        let tags = attr("tags")
+            .split(',')
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .map(String::from)
+            .collect();
+        let title = unescape_html(&cap["title"]);
+        batch.bookmarks.push(bookmark(&url, &title, &attr("time_added"), tags, unread));
+    }
+    batch
+}
+
+/// The csv export has `title,url,time_added,tags,status` columns, tags separated with `|`
+fn parse_csv(content: &str) -> Result<Batch> {
+    let mut reader = csv::Reader::from_reader(content.as_bytes());
+    let headers = reader.headers()?.clone();
+    let column = |name: &str| headers.iter().position(|h| h.trim() == name);
+    let url_col = column("url").context("Pocket csv export has no url column")?;
+    let (title_col, time_col, tags_col, status_col) =
+        (column("title"), column("time_added"), column("tags"), column("status"));
+
+    let mut batch = Batch::default();
+    for (i, record) in reader.records().enumerate() {
+        // line 1 is the header
+        let line = i + 2;
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                batch.skipped.push(format!("pocket csv line {}: {}", line, e));
+                continue;
+            }
+        };
+        let get = |col: Option<usize>| col.and_then(|c| record.get(c)).unwrap_or_default();
+        let url = get(Some(url_col)).trim();
+        if url.is_empty() {
+            batch.skipped.push(format!("pocket csv line {}: no url", line));
+            continue;
+        }
+        let tags = get(tags_col)
+            .split('|')
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .map(String::from)
+            .collect();
+        let unread = get(status_col).trim() == "unread";
+        batch.bookmarks.push(bookmark(url, get(title_col), get(time_col), tags, unread));
+    }
+    Ok(batch)
+}
+
+#[test]
+fn parses_html_export() {
+    let content = r#"<!DOCTYPE html>
+<html><head><title>Pocket Export</title></head><body>
+<h1>Unread</h1>
+<ul>
+<li><a href="https://docs.rs/?a=1&amp;b=2" time_added="1609459199" tags="rust,docs">Docs &amp; more</a></li>
+</ul>
+<h1>Read Archive</h1>
+<ul>
+<li><a href="https://example.com/" time_added="1609459200" tags="">https://example.com/</a></li>
+</ul>
+</body></html>"#;
+
+    let batch = parse_html(content);
+    assert_eq!(batch.bookmarks.len(), 2);
+    let docs = &batch.bookmarks[0];
+    assert_eq!(docs.url, "https://docs.rs/?a=1&b=2");
+    assert_eq!(docs.name.as_deref(), Some("Docs & more"));
+    assert_eq!(docs.tags, vec!["rust", "docs"]);
+    assert_eq!(docs.added_at.as_deref(), Some("2020-12-31 23:59:59"));
+    let example = &batch.bookmarks[1];
+    assert_eq!(example.name, None);
+    assert!(example.tags.is_empty());
+    assert_eq!((docs.status, example.status), (Some(ReadStatus::Unread), Some(ReadStatus::Read)));
+}
+
+#[test]
+fn parses_csv_export() -> Result<()> {
+    let content = "title,url,time_added,tags,status\n\
+                   Docs,https://docs.rs/,1609459199,rust|docs,unread\n\
+                   ,,1609459199,,archive\n\
+                   Example,https://example.com/,1609459200,,archive\n";
+
+    let batch = parse_csv(content)?;
+    assert_eq!(batch.bookmarks.len(), 2);
+    assert_eq!(batch.skipped, vec!["pocket csv line 3: no url"]);
+    assert_eq!(batch.bookmarks[0].tags, vec!["rust", "docs"]);
+    assert!(batch.bookmarks[1].tags.is_empty());
+    assert_eq!(batch.bookmarks[0].status, Some(ReadStatus::Unread));
+    assert_eq!(batch.bookmarks[1].status, Some(ReadStatus::Read));
+
+    Ok(())
+}
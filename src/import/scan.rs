@@ -0,0 +1,102 @@
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use anyhow::{Context, Result};
+use regex::Regex;
+
+use super::{unescape_html, Batch};
+use crate::NewBookmark;
+
+/// Pull the URLs out of notes, chat logs and the like: markdown links and html anchors, which
+/// also give the name, and bare URLs in the text. Each URL is only taken once, across files.
+pub fn read(paths: &[PathBuf]) -> Result<Batch> {
+    let mut batch = Batch::default();
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for path in paths {
+        let content = fs::read_to_string(path).with_context(|| format!("Couldn't read {}", path.display()))?;
+        for (url, name) in extract(&content) {
+            match seen.get(&url) {
+                // a later mention may be the one giving it a name
+                Some(&i) => {
+                    if batch.bookmarks[i].name.is_none() {
+                        batch.bookmarks[i].name = name;
+                    }
+                }
+                None => {
+                    seen.insert(url.clone(), batch.bookmarks.len());
+                    batch.bookmarks.push(NewBookmark { url, name, ..Default::default() });
+                }
+            }
+        }
+    }
+    Ok(batch)
+}
+
+/// Text trailing a bare URL which more likely ends the sentence than belongs to the URL
+fn trim_url(url: &str) -> &str {
+    let mut url = url.trim_end_matches(['.', ',', ';', ':', '!', '?', '\'', '"']);
+    // `(see https://example.com)` but not `https://en.wikipedia.org/wiki/Rust_(language)`
+    while url.ends_with(')') && url.matches(')').count() > url.matches('(').count() {
+        url = url[..url.len() - 1].trim_end_matches(['.', ',', ';', ':', '!', '?']);
+    }
+    url
+}
+
+/// URLs in `content` in the order they appear, with the link text when there is one
+fn extract(content: &str) -> Vec<(String, Option<String>)> {
+    let markdown_re = Regex::new(r#"\[(?P<text>[^\]]*)\]\(\s*<?(?P<url>https?://[^)\s>]+)>?(?:\s+"[^"]*")?\s*\)"#)
+        .expect("Failed to create regex");
+    let anchor_re = Regex::new(r#"(?is)<a\s[^>]*?href\s*=\s*["'](?P<url>https?://[^"']+)["'][^>]*>(?P<text>.*?)</a>"#)
+        .expect("Failed to create regex");
+    let tag_re = Regex::new(r"(?s)<[^>]*>").expect("Failed to create regex");
+    let bare_re = Regex::new(r#"https?://[^\s<>"'\]\[`]+"#).expect("Failed to create regex");
+
+    let name = |text: &str| Some(text.split_whitespace().collect::<Vec<_>>().join(" ")).filter(|t| !t.is_empty());
+    let mut links: Vec<(usize, usize, String, Option<String>)> = vec![];
+    for cap in markdown_re.captures_iter(content) {
+        let m = cap.get(0).unwrap();
+        links.push((m.start(), m.end(), cap["url"].to_owned(), name(&cap["text"])));
+    }
+    for cap in anchor_re.captures_iter(content) {
+        let m = cap.get(0).unwrap();
+        if links.iter().any(|(start, end, _, _)| m.start() < *end && *start < m.end()) {
+            continue;
+        }
+        let text = unescape_html(&tag_re.replace_all(&cap["text"], ""));
+        links.push((m.start(), m.end(), unescape_html(&cap["url"]), name(&text)));
+    }
+    let mut bare = vec![];
+    for m in bare_re.find_iter(content) {
+        if links.iter().any(|(start, end, _, _)| m.start() < *end && *start < m.end()) {
+            continue;
+        }
+        let url = trim_url(m.as_str());
+        if url.len() > "https://".len() {
+            bare.push((m.start(), m.end(), url.to_owned(), None));
+        }
+    }
+    links.extend(bare);
+    links.sort_by_key(|(start, _, _, _)| *start);
+
+    links.into_iter().map(|(_, _, url, name)| (url, name)).collect()
+}
+
+#[test]
+fn extracts_links_from_text_markdown_and_html() {
+    let content = "Notes for the week.\n\
+                   Read [The Book](https://doc.rust-lang.org/book/ \"title\") and <a class=\"x\" href=\"https://docs.rs/?a=1&amp;b=2\"><b>Docs</b> &amp; more</a>.\n\
+                   Someone shared https://blog.rust-lang.org/, also (see https://en.wikipedia.org/wiki/Rust_(programming_language)).\n\
+                   <https://example.com/path?q=1> and again https://doc.rust-lang.org/book/\n";
+
+    let urls = extract(content);
+    assert_eq!(
+        urls,
+        [
+            ("https://doc.rust-lang.org/book/".to_owned(), Some("The Book".to_owned())),
+            ("https://docs.rs/?a=1&b=2".to_owned(), Some("Docs & more".to_owned())),
+            ("https://blog.rust-lang.org/".to_owned(), None),
+            ("https://en.wikipedia.org/wiki/Rust_(programming_language)".to_owned(), None),
+            ("https://example.com/path?q=1".to_owned(), None),
+            ("https://doc.rust-lang.org/book/".to_owned(), None),
+        ]
+    );
+}
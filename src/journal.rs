@@ -0,0 +1,244 @@
+use anyhow::{bail, ensure, Result};
+use rusqlite::{params, Connection, OptionalExtension, Params, Transaction};
+
+/// A recorded mutation, as listed by `bmark history`
+#[derive(Debug)]
+pub struct Operation {
+    pub id: i64,
+    pub kind: String,
+    pub summary: String,
+    pub rows: usize,
+    pub created_at: String,
+    pub undone_at: Option<String>,
+}
+
+/// Records the before and after images of every row a mutation touches, so that the whole
+/// mutation can later be undone as one unit. Rows are kept as json objects built by sqlite
+/// itself, which keeps this working for columns added by later migrations.
+pub(crate) struct Journal {
+    operation_id: i64,
+    touched: Vec<(String, String)>,
+}
+
+/// (column names, primary key column names) of `table`
+fn columns(conn: &Connection, table: &str) -> Result<(Vec<String>, Vec<String>)> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(1)?, row.get::<_, i64>(5)?)))?;
+
+    let mut cols = vec![];
+    let mut pk_cols = vec![];
+    for row in rows {
+        let (name, pk) = row?;
+        if pk > 0 {
+            pk_cols.push(name.clone());
+        }
+        cols.push(name);
+    }
+    ensure!(!pk_cols.is_empty(), "Table {} can't be journaled without a primary key", table);
+
+    Ok((cols, pk_cols))
+}
+
+fn json_object_expr(cols: &[String]) -> String {
+    let pairs = cols
+        .iter()
+        .map(|c| format!("'{}', {}", c, c))
+        .collect::<Vec<_>>();
+    format!("json_object({})", pairs.join(", "))
+}
+
+fn key_condition(pk_cols: &[String]) -> String {
+    pk_cols
+        .iter()
+        .map(|c| format!("{} = json_extract(?1, '$.{}')", c, c))
+        .collect::<Vec<_>>()
+        .join(" AND ")
+}
+
+/// Current image of the row identified by `key`, `None` if there is no such row
+fn image(conn: &Connection, table: &str, key: &str) -> Result<Option<String>> {
+    let (cols, pk_cols) = columns(conn, table)?;
+    let stmt = format!(
+        "SELECT {} FROM {} WHERE {}",
+        json_object_expr(&cols),
+        table,
+        key_condition(&pk_cols)
+    );
+    Ok(conn.query_row(&stmt, params![key], |row| row.get(0)).optional()?)
+}
+
+/// Put the row identified by `key` back to `image`, removing it when `image` is `None`
+fn restore_image(conn: &Connection, table: &str, key: &str, image: Option<&str>) -> Result<()> {
+    let (cols, pk_cols) = columns(conn, table)?;
+    conn.execute(
+        &format!("DELETE FROM {} WHERE {}", table, key_condition(&pk_cols)),
+        params![key],
+    )?;
+    if let Some(image) = image {
+        let values = cols
+            .iter()
+            .map(|c| format!("json_extract(?1, '$.{}')", c))
+            .collect::<Vec<_>>();
+        conn.execute(
+            &format!("INSERT INTO {} ({}) SELECT {}", table, cols.join(", "), values.join(", ")),
+            params![image],
+        )?;
+    }
+    Ok(())
+}
+
+impl Journal {
+    pub(crate) fn begin(tx: &Transaction, kind: &str, summary: &str) -> Result<Self> {
+        tx.execute(
+            "INSERT INTO operation (kind, summary) VALUES (?1, ?2)",
+            params![kind, summary],
+        )?;
+        Ok(Journal {
+            operation_id: tx.last_insert_rowid(),
+            touched: vec![],
+        })
+    }
+
+    /// Snapshot the row of `table` with the given primary key values before it gets changed
+    /// (or created). Touching the same row twice keeps the first snapshot.
+    pub(crate) fn touch(&mut self, tx: &Transaction, table: &str, key: &[(&str, &str)]) -> Result<()> {
+        let pairs = key
+            .iter()
+            .flat_map(|(col, val)| [col.to_string(), val.to_string()])
+            .collect::<Vec<_>>();
+        let placeholders = vec!["?"; pairs.len()].join(", ");
+        let key: String = tx.query_row(
+            &format!("SELECT json_object({})", placeholders),
+            rusqlite::params_from_iter(pairs),
+            |row| row.get(0),
+        )?;
+        self.touch_key(tx, table, key)
+    }
+
+    /// Snapshot every row of `table` matching `condition` before they get changed
+    pub(crate) fn touch_where<P: Params>(
+        &mut self,
+        tx: &Transaction,
+        table: &str,
+        condition: &str,
+        params: P,
+    ) -> Result<()> {
+        let (_, pk_cols) = columns(tx, table)?;
+        let keys = {
+            let mut stmt = tx.prepare(&format!(
+                "SELECT {} FROM {} WHERE {}",
+                json_object_expr(&pk_cols),
+                table,
+                condition
+            ))?;
+            let rows = stmt.query_map(params, |row| row.get::<_, String>(0))?;
+            rows.collect::<Result<Vec<_>, _>>()?
+        };
+        for key in keys {
+            self.touch_key(tx, table, key)?;
+        }
+        Ok(())
+    }
+
+    fn touch_key(&mut self, tx: &Transaction, table: &str, key: String) -> Result<()> {
+        if self.touched.iter().any(|(t, k)| t == table && *k == key) {
+            return Ok(());
+        }
+        let before = image(tx, table, &key)?;
+        tx.execute(
+            "INSERT INTO operation_row (operation_id, seq, table_name, row_key, before) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![self.operation_id, self.touched.len(), table, key, before],
+        )?;
+        self.touched.push((table.to_owned(), key));
+        Ok(())
+    }
+
+    /// Record the after images of the touched rows, dropping the ones which didn't change
+    pub(crate) fn finish(self, tx: &Transaction) -> Result<()> {
+        for (table, key) in &self.touched {
+            let after = image(tx, table, key)?;
+            tx.execute(
+                "UPDATE operation_row SET after=?1 WHERE operation_id=?2 AND table_name=?3 AND row_key=?4",
+                params![after, self.operation_id, table, key],
+            )?;
+        }
+        tx.execute(
+            "DELETE FROM operation_row WHERE operation_id=?1 AND before IS after",
+            params![self.operation_id],
+        )?;
+        // an operation which changed nothing has nothing to undo either
+        tx.execute(
+            "DELETE FROM operation WHERE id=?1 AND NOT EXISTS (SELECT 1 FROM operation_row WHERE operation_id=?1)",
+            params![self.operation_id],
+        )?;
+        Ok(())
+    }
+}
+
+/// Revert the latest operation which isn't undone yet, returning it
+pub(crate) fn undo_last(conn: &mut Connection) -> Result<Option<Operation>> {
+    let tx = conn.transaction()?;
+    // rows get deleted and put back one by one, so only check the references once all are back
+    tx.pragma_update(None, "defer_foreign_keys", true)?;
+    let Some(operation) = operations(&tx, "undone_at IS NULL", 1)?.pop() else {
+        return Ok(None);
+    };
+
+    let rows = {
+        let mut stmt = tx.prepare(
+            "SELECT table_name, row_key, before, after FROM operation_row WHERE operation_id=?1 ORDER BY seq DESC",
+        )?;
+        let rows = stmt.query_map(params![operation.id], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, Option<String>>(3)?,
+            ))
+        })?;
+        rows.collect::<Result<Vec<_>, _>>()?
+    };
+
+    // refuse rather than clobber changes made by later operations which were not undone
+    for (table, key, _, after) in &rows {
+        if image(&tx, table, key)? != *after {
+            bail!(
+                "Can't undo #{} ({}), a {} row it touched was changed afterwards",
+                operation.id,
+                operation.summary,
+                table
+            );
+        }
+    }
+    for (table, key, before, _) in &rows {
+        restore_image(&tx, table, key, before.as_deref())?;
+    }
+    tx.execute(
+        "UPDATE operation SET undone_at=current_timestamp WHERE id=?1",
+        params![operation.id],
+    )?;
+    tx.commit()?;
+
+    Ok(Some(operation))
+}
+
+/// Operations matching `condition`, newest first
+pub(crate) fn operations(conn: &Connection, condition: &str, limit: usize) -> Result<Vec<Operation>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT o.id, o.kind, o.summary, o.created_at, o.undone_at, \
+         (SELECT count(*) FROM operation_row r WHERE r.operation_id=o.id) \
+         FROM operation o WHERE {} ORDER BY o.id DESC LIMIT ?1",
+        condition
+    ))?;
+    let rows = stmt.query_map(params![limit], |row| {
+        Ok(Operation {
+            id: row.get(0)?,
+            kind: row.get(1)?,
+            summary: row.get(2)?,
+            created_at: row.get(3)?,
+            undone_at: row.get(4)?,
+            rows: row.get(5)?,
+        })
+    })?;
+    Ok(rows.collect::<Result<Vec<_>, _>>()?)
+}
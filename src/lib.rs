@@ -1,12 +1,34 @@
 use std::{
-    collections::HashMap, fs::{self, File}, path::{Path, PathBuf}, time::{SystemTime, UNIX_EPOCH}
+    collections::{HashMap, HashSet}, fs::{self, File}, path::{Path, PathBuf}, time::{SystemTime, UNIX_EPOCH}
 };
 
-use anyhow::{Context, Result};
-use rusqlite::{params, types::Type, Connection, Error::{self, QueryReturnedNoRows}, OptionalExtension, Row};
+use anyhow::{bail, ensure, Context, Result};
+use journal::Journal;
+use rusqlite::{params, Connection, Error::QueryReturnedNoRows, OptionalExtension, Params, Transaction};
+use serde::{Deserialize, Serialize};
 use uuid::{NoContext, Timestamp};
 
-mod date;
+pub mod archive;
+pub mod browser;
+pub mod check;
+pub mod date;
+pub mod export;
+pub mod favicon;
+pub mod feed;
+pub mod fetch;
+pub mod import;
+mod journal;
+pub mod publish;
+pub mod rescue;
+
+use archive::{GcReport, Snapshot};
+use check::LinkStatus;
+use date::Datetime;
+use favicon::{FaviconOptions, Icon};
+use fetch::Metadata;
+use import::{Batch, ImportReport};
+use rescue::{Alternate, ArchiveProvider, ArchivedCopy};
+pub use journal::Operation;
 
 pub enum BMarkTask {
     Setup {
@@ -40,11 +62,164 @@ pub enum ListColumn {
     Desc,
 }
 
+#[derive(Clone, Copy, Default)]
 pub enum TagMode {
     All,
+    #[default]
     Any,
 }
 
+/// Where a bookmark is in the reading queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReadStatus {
+    Unread,
+    Reading,
+    Read,
+    Archived,
+}
+
+impl ReadStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReadStatus::Unread => "unread",
+            ReadStatus::Reading => "reading",
+            ReadStatus::Read => "read",
+            ReadStatus::Archived => "archived",
+        }
+    }
+
+    /// Column of `bmark` holding when the bookmark last got this status
+    fn column(&self) -> &'static str {
+        match self {
+            ReadStatus::Unread => "unread_at",
+            ReadStatus::Reading => "reading_at",
+            ReadStatus::Read => "read_at",
+            ReadStatus::Archived => "archived_at",
+        }
+    }
+}
+
+impl std::str::FromStr for ReadStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "unread" => Ok(ReadStatus::Unread),
+            "reading" => Ok(ReadStatus::Reading),
+            "read" => Ok(ReadStatus::Read),
+            "archived" => Ok(ReadStatus::Archived),
+            _ => bail!("Unknown status {}, expected unread, reading, read or archived", s),
+        }
+    }
+}
+
+/// What the last `check` of a bookmark's link found
+#[derive(Clone, Copy)]
+pub enum LinkFilter {
+    Broken,
+    Redirected,
+}
+
+#[derive(Clone, Copy, Default)]
+pub enum SortBy {
+    #[default]
+    Added,
+    Visits,
+    LastVisited,
+}
+
+/// How `list` should pick and order the bookmarks
+#[derive(Clone, Default)]
+pub struct ListOptions {
+    pub sort: SortBy,
+    /// Only bookmarks with these tags, any or all of them as `tag_mode` says
+    pub tags: Vec<String>,
+    pub tag_mode: TagMode,
+    /// Only bookmarks in this category or one nested under it, like `dev` for `dev/rust`
+    pub category: Option<String>,
+    pub link: Option<LinkFilter>,
+    pub status: Option<ReadStatus>,
+    pub pinned: bool,
+    /// Only bookmarks rated this many stars or more
+    pub min_rating: Option<u8>,
+}
+
+/// Condition on the latest `link_status` row of bookmark `b`, if that check was of the URL
+/// the bookmark has now
+const LATEST_LINK_STATUS: &str =
+    "EXISTS (SELECT 1 FROM link_status ls WHERE ls.id=(SELECT max(id) FROM link_status WHERE bmark_id=b.id) AND ls.url=b.url AND {})";
+
+/// A bookmark row along with the names of its tags
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Bookmark {
+    pub id: String,
+    pub url: String,
+    pub name: Option<String>,
+    pub tags: Vec<String>,
+    pub desc: Option<String>,
+    pub category: Option<String>,
+    pub added_at: String,
+    pub deleted_at: Option<String>,
+    /// None for bookmarks which never were in the reading queue
+    pub status: Option<ReadStatus>,
+    pub unread_at: Option<String>,
+    pub reading_at: Option<String>,
+    pub read_at: Option<String>,
+    pub archived_at: Option<String>,
+    /// Pinned bookmarks are listed before the others
+    pub pinned: bool,
+    /// 1 to 5 stars
+    pub rating: Option<u8>,
+    pub notes: Vec<Note>,
+    /// Newest first
+    pub alternates: Vec<Alternate>,
+    pub reminder: Option<Reminder>,
+    /// When the bookmark was opened, oldest first
+    pub visits: Vec<String>,
+    pub page_meta: Option<PageMeta>,
+    /// Oldest first
+    pub revisions: Vec<Revision>,
+    /// Every check of the link, newest first
+    pub link_statuses: Vec<LinkStatus>,
+}
+
+/// What the bookmark's page said about itself when it was last fetched
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PageMeta {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub canonical_url: Option<String>,
+    pub lang: Option<String>,
+    pub fetched_at: String,
+}
+
+/// When to come back to a bookmark
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Reminder {
+    /// Left out of dumps, where the reminder is part of its bookmark
+    #[serde(skip)]
+    pub bmark_id: String,
+    /// `yyyy-mm-dd hh:mm:ss` in UTC
+    pub due_at: String,
+    pub created_at: String,
+    /// Times it was put off
+    pub snoozed: u32,
+}
+
+/// A markdown note on a bookmark, numbered in the order they were written
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Note {
+    pub seq: i64,
+    pub body: String,
+    pub created_at: String,
+    pub updated_at: Option<String>,
+}
+
 #[allow(dead_code)]
 #[derive(Debug)]
 struct BookmarkAll {
@@ -55,35 +230,79 @@ struct BookmarkAll {
     category: Option<String>,
 }
 
+#[allow(dead_code)]
 #[derive(Debug)]
 struct BookmarkUrl {
     url: String,
 }
 
+#[allow(dead_code)]
 #[derive(Debug)]
 struct BookmarkDesc {
     url: String,
     desc: Option<String>,
 }
 
+#[allow(dead_code)]
 #[derive(Debug)]
 struct BookmarkTag {
     url: String,
     tag: Vec<String>,
 }
 
-enum BookmarkView {
-    All(Vec<BookmarkAll>),
-    Url(Vec<BookmarkUrl>),
-    Desc(Vec<BookmarkDesc>),
-    Tags(Vec<BookmarkTag>),
-}
-
 fn create_table(conn: &Connection, schema: &str) -> Result<()> {
     conn.execute(schema, ())?;
     Ok(())
 }
 
+/// Schema changes applied on top of the tables created in `setup`, oldest first.
+/// sqlite's `user_version` keeps count of how many of these a db has already seen.
+const MIGRATIONS: &[&str] = &[
+    "ALTER TABLE bmark ADD COLUMN deleted_at TEXT;",
+    "CREATE TABLE operation ( id INTEGER PRIMARY KEY AUTOINCREMENT, kind TEXT NOT NULL, summary TEXT NOT NULL, created_at TEXT NOT NULL DEFAULT current_timestamp, undone_at TEXT);
+     CREATE TABLE operation_row ( operation_id INTEGER NOT NULL, seq INTEGER NOT NULL, table_name TEXT NOT NULL, row_key TEXT NOT NULL, before TEXT, after TEXT, FOREIGN KEY (operation_id) REFERENCES operation(id), PRIMARY KEY (operation_id, seq));",
+    // existing bookmarks start their history with what they look like today
+    "CREATE TABLE revision ( bmark_id TEXT NOT NULL, rev INTEGER NOT NULL, url TEXT NOT NULL, name TEXT, description TEXT, category TEXT, tags TEXT NOT NULL, created_at TEXT NOT NULL DEFAULT current_timestamp, FOREIGN KEY (bmark_id) REFERENCES bmark(id), PRIMARY KEY (bmark_id, rev));
+     INSERT INTO revision (bmark_id, url, name, description, category, tags, rev, created_at)
+     SELECT b.id, b.url, b.name, b.description, b.category,
+     (SELECT json_group_array(name) FROM (SELECT t.name FROM bmark_tag bt JOIN tag t ON t.id=bt.tag_id WHERE bt.bmark_id=b.id ORDER BY t.name)),
+     1, b.added_at FROM bmark b;",
+    "CREATE TABLE visit ( id INTEGER PRIMARY KEY AUTOINCREMENT, bmark_id TEXT NOT NULL, visited_at TEXT NOT NULL DEFAULT current_timestamp, FOREIGN KEY (bmark_id) REFERENCES bmark(id));",
+    "CREATE TABLE page_meta ( bmark_id TEXT PRIMARY KEY, title TEXT, description TEXT, canonical_url TEXT, lang TEXT, fetched_at TEXT NOT NULL DEFAULT current_timestamp, FOREIGN KEY (bmark_id) REFERENCES bmark(id));",
+    "CREATE TABLE link_status ( id INTEGER PRIMARY KEY AUTOINCREMENT, bmark_id TEXT NOT NULL, status INTEGER, final_url TEXT NOT NULL, permanent INTEGER NOT NULL, error TEXT, checked_at TEXT NOT NULL, FOREIGN KEY (bmark_id) REFERENCES bmark(id));",
+    "CREATE TABLE archive ( id INTEGER PRIMARY KEY AUTOINCREMENT, bmark_id TEXT NOT NULL, url TEXT NOT NULL, hash TEXT NOT NULL, path TEXT NOT NULL, size INTEGER NOT NULL, assets INTEGER NOT NULL, archived_at TEXT NOT NULL DEFAULT current_timestamp, FOREIGN KEY (bmark_id) REFERENCES bmark(id));",
+    "CREATE VIRTUAL TABLE content_fts USING fts5(bmark_id UNINDEXED, source UNINDEXED, body, tokenize='unicode61 remove_diacritics 2');",
+    "CREATE TABLE alternate ( bmark_id TEXT NOT NULL, url TEXT NOT NULL, source TEXT NOT NULL, captured_at TEXT, added_at TEXT NOT NULL DEFAULT current_timestamp, FOREIGN KEY (bmark_id) REFERENCES bmark(id), PRIMARY KEY (bmark_id, url));",
+    "CREATE TABLE favicon ( domain TEXT PRIMARY KEY, mime TEXT, data BLOB, fetched_at TEXT NOT NULL DEFAULT current_timestamp);",
+    "ALTER TABLE bmark ADD COLUMN status TEXT;
+     ALTER TABLE bmark ADD COLUMN unread_at TEXT;
+     ALTER TABLE bmark ADD COLUMN reading_at TEXT;
+     ALTER TABLE bmark ADD COLUMN read_at TEXT;
+     ALTER TABLE bmark ADD COLUMN archived_at TEXT;",
+    "ALTER TABLE bmark ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0;
+     ALTER TABLE bmark ADD COLUMN rating INTEGER CHECK (rating BETWEEN 1 AND 5);",
+    "CREATE TABLE note ( bmark_id TEXT NOT NULL, seq INTEGER NOT NULL, body TEXT NOT NULL, created_at TEXT NOT NULL DEFAULT current_timestamp, updated_at TEXT, FOREIGN KEY (bmark_id) REFERENCES bmark(id), PRIMARY KEY (bmark_id, seq));",
+    "CREATE TABLE reminder ( bmark_id TEXT PRIMARY KEY, due_at TEXT NOT NULL, created_at TEXT NOT NULL DEFAULT current_timestamp, snoozed INTEGER NOT NULL DEFAULT 0, FOREIGN KEY (bmark_id) REFERENCES bmark(id));",
+    // earlier checks were of the URL the bookmark has today, as far as can be told
+    "ALTER TABLE link_status ADD COLUMN url TEXT;
+     UPDATE link_status SET url=(SELECT url FROM bmark WHERE id=link_status.bmark_id);",
+];
+
+fn migrate(conn: &mut Connection) -> Result<()> {
+    let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version >= MIGRATIONS.len() {
+        return Ok(());
+    }
+
+    let tx = conn.transaction()?;
+    for migration in &MIGRATIONS[version..] {
+        tx.execute_batch(migration)
+            .with_context(|| format!("Failed to apply migration: {}", migration))?;
+    }
+    tx.pragma_update(None, "user_version", MIGRATIONS.len())?;
+    Ok(tx.commit()?)
+}
+
 pub struct BMark {
     conn: Connection,
 }
@@ -94,16 +313,19 @@ impl BMark {
         P: AsRef<Path>,
     {
         if perform_setup {
-            _ = fs::create_dir_all(path.as_ref().parent().unwrap())?;
+            fs::create_dir_all(path.as_ref().parent().unwrap())?;
             _ = File::create(path.as_ref())?;
         }
-        return Ok(BMark {
-            conn: get_db_connection(Some(&path.as_ref().to_path_buf()))
-                .expect("Connection to db needs to be created"),
-        });
+        let mut conn = get_db_connection(Some(&path.as_ref().to_path_buf()))
+            .expect("Connection to db needs to be created");
+        // a fresh db gets migrated once `setup` has created the tables
+        if !perform_setup {
+            migrate(&mut conn)?;
+        }
+        Ok(BMark { conn })
     }
 
-    pub fn setup(&self) -> Result<()> {
+    pub fn setup(&mut self) -> Result<()> {
         let bmark_schema = "CREATE TABLE bmark ( id TEXT PRIMARY KEY, url TEXT NOT NULL, name TEXT, description TEXT, category TEXT, added_at TEXT NOT NULL DEFAULT current_timestamp);";
         let tag_schema = "CREATE TABLE tag ( id TEXT PRIMARY KEY, name TEXT UNIQUE NOT NULL, added_at TEXT NOT NULL DEFAULT current_timestamp);";
         let bmark_tag_schema = "CREATE TABLE bmark_tag ( bmark_id TEXT, tag_id TEXT, created_at TEXT NOT NULL DEFAULT current_timestamp, FOREIGN KEY (bmark_id) REFERENCES bmark(id), FOREIGN KEY (tag_id) REFERENCES tag(id), PRIMARY KEY (bmark_id, tag_id));";
@@ -112,7 +334,7 @@ impl BMark {
         create_table(&self.conn, tag_schema)?;
         create_table(&self.conn, bmark_tag_schema)?;
 
-        Ok(())
+        migrate(&mut self.conn)
     }
 
     pub fn insert(
@@ -122,251 +344,1201 @@ impl BMark {
         tags: Vec<&str>,
         desc: Option<&str>,
         category: Option<&str>,
-    ) -> Result<()> {
-        let mut tag_uuids: Vec<String> = vec![];
-
-        let tags_not_present = tags.iter().filter(|&&t| {
-            let query_tag = format!("Select id from tag where name='{}'", t);
-            let tag_id = self.conn.query_row(&query_tag, [], |row| row.get::<_, String>(0)).optional().unwrap();
-            if let Some(tag_id) = &tag_id {
-                tag_uuids.push(tag_id.clone());
+    ) -> Result<String> {
+        let new = NewBookmark {
+            url: url.to_owned(),
+            name: name.map(String::from),
+            tags: tags.into_iter().map(String::from).collect(),
+            desc: desc.map(String::from),
+            category: category.map(String::from),
+            ..Default::default()
+        };
+        self.add(&new, None)
+    }
+
+    /// Add a bookmark along with the metadata fetched from its page, if any, returning its id
+    pub fn add(&mut self, new: &NewBookmark, meta: Option<&Metadata>) -> Result<String> {
+        let tx = self.conn.transaction()?;
+        let mut journal = Journal::begin(&tx, "add", &new.url)?;
+        let bmark_uuid = add_bookmark(&tx, &mut journal, new)?;
+        if let Some(meta) = meta {
+            journal.touch(&tx, "page_meta", &[("bmark_id", &bmark_uuid)])?;
+            tx.execute(
+                "INSERT OR REPLACE INTO page_meta (bmark_id, title, description, canonical_url, lang) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![bmark_uuid, meta.title, meta.description, meta.canonical_url, meta.lang],
+            )?;
+        }
+        journal.finish(&tx)?;
+        tx.commit()?;
+        Ok(bmark_uuid)
+    }
+
+    /// What the bookmark's page said about itself when it was last fetched
+    pub fn page_meta(&self, id: &str) -> Result<Option<PageMeta>> {
+        Ok(self.bookmark(id)?.page_meta)
+    }
+
+    /// Add the bookmarks read by an importer as a single operation, skipping the URLs (and ids)
+    /// which are already bookmarked, trashed ones included, so importing the same source again
+    /// is harmless
+    pub fn import(&mut self, source: &str, batch: Batch) -> Result<ImportReport> {
+        let mut report = ImportReport {
+            skipped: batch.skipped,
+            ignored: batch.ignored,
+            ..Default::default()
+        };
+
+        let tx = self.conn.transaction()?;
+        let mut journal = Journal::begin(&tx, "import", source)?;
+        for new in &batch.bookmarks {
+            let known = tx
+                .query_row("SELECT 1 FROM bmark WHERE url=?1 OR id=?2", params![new.url, new.id], |_| Ok(()))
+                .optional()?;
+            if known.is_some() {
+                report.duplicates.push(new.url.clone());
+                continue;
             }
-            return tag_id.is_none();
-        }).collect::<Vec<_>>();
+            add_bookmark(&tx, &mut journal, new)?;
+            report.added += 1;
+        }
+        journal.finish(&tx)?;
+        tx.commit()?;
+
+        Ok(report)
+    }
 
-        // insert bmark
-        let epoch = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("Duration befor Unix Epoch");
-        let ts = Timestamp::from_unix(NoContext, epoch.as_secs(), 0);
-        let bmark_uuid = uuid::Uuid::new_v7(ts).hyphenated().to_string();
+    /// Whether the URL is bookmarked already, in trash or not, the way `import` tells duplicates
+    pub fn is_bookmarked(&self, url: &str) -> Result<bool> {
+        Ok(!self.query_ids("url=?1", url)?.is_empty())
+    }
 
+    /// Change the given fields of a bookmark, an empty value clears the field
+    pub fn edit(&mut self, id: &str, edit: &BookmarkEdit) -> Result<()> {
         let tx = self.conn.transaction()?;
-        tx.execute("INSERT INTO bmark (id, url, name, description, category) VALUES (?1, ?2, ?3, ?4, ?5)",
-             params![bmark_uuid, url, name, desc, category])?;
+        let mut journal = Journal::begin(&tx, "edit", id)?;
+        apply_edit(&tx, &mut journal, id, edit)?;
+        journal.finish(&tx)?;
+        Ok(tx.commit()?)
+    }
 
-        for tag in tags_not_present {
-            let uuid = uuid::Uuid::new_v7(ts).hyphenated().to_string();
-            tag_uuids.push(uuid.clone());
-            tx.execute("INSERT INTO tag (id, name) VALUES(?1, ?2)", params![uuid, tag])?;
-        }
+    /// Every recorded state of the bookmark, oldest first
+    pub fn revisions(&self, id: &str) -> Result<Vec<Revision>> {
+        Ok(self.revisions_where("bmark_id=?1", params![id])?.into_iter().map(|(_, revision)| revision).collect())
+    }
 
-        // make bmark-tag relation
-        for tag_uuid in tag_uuids {
-            tx.execute("INSERT INTO bmark_tag (bmark_id, tag_id) VALUES(?1, ?2)", params![bmark_uuid, tag_uuid])?;
+    /// Revisions matching `condition` along with the id of their bookmark, oldest first
+    fn revisions_where<P: Params>(&self, condition: &str, params: P) -> Result<Vec<(String, Revision)>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT bmark_id, rev, url, name, description, category, tags, created_at FROM revision WHERE {} ORDER BY bmark_id, rev",
+            condition
+        ))?;
+        let rows = stmt.query_map(params, |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                Revision {
+                    rev: row.get(1)?,
+                    url: row.get(2)?,
+                    name: row.get(3)?,
+                    desc: row.get(4)?,
+                    category: row.get(5)?,
+                    tags: vec![],
+                    created_at: row.get(7)?,
+                },
+                row.get::<_, String>(6)?,
+            ))
+        })?;
+
+        let mut revisions = vec![];
+        for row in rows {
+            let (bmark_id, mut revision, tags) = row?;
+            revision.tags = json_array(&self.conn, &tags)?;
+            revisions.push((bmark_id, revision));
         }
+        Ok(revisions)
+    }
 
+    /// Bring the fields and tags of the bookmark back to how they were at revision `rev`
+    pub fn revert(&mut self, id: &str, rev: i64) -> Result<()> {
+        let revision = self
+            .revisions(id)?
+            .into_iter()
+            .find(|r| r.rev == rev)
+            .with_context(|| format!("Bookmark {} has no revision {}", id, rev))?;
+        let current = self.bookmark(id)?;
+
+        let edit = BookmarkEdit {
+            url: Some(revision.url),
+            name: Some(revision.name.unwrap_or_default()),
+            desc: Some(revision.desc.unwrap_or_default()),
+            category: Some(revision.category.unwrap_or_default()),
+            add_tags: revision.tags.iter().filter(|t| !current.tags.contains(t)).cloned().collect(),
+            remove_tags: current.tags.into_iter().filter(|t| !revision.tags.contains(t)).collect(),
+        };
+
+        let tx = self.conn.transaction()?;
+        let mut journal = Journal::begin(&tx, "revert", &format!("{} to rev {}", id, rev))?;
+        apply_edit(&tx, &mut journal, id, &edit)?;
+        journal.finish(&tx)?;
         Ok(tx.commit()?)
     }
 
-    fn make_row(row: &Row, range: usize) -> Result<String, Error> {
-        let mut res = String::new();
-        for i in 0..range {
-            res.push_str(match &row.get::<_, String>(i) {
-                Ok(s) => {
-                    s
-                },
-                Err(e) => {
-                    match e {
-                        Error::InvalidColumnType(sz, name, t) => {
-                            match t {
-                                Type::Null => "",
-                                _ => return Err(Error::InvalidColumnType(sz.to_owned(), name.to_owned(), t.to_owned()))
-                            }
-                        },
-                        e => panic!("Error occurred during retrieving data: {}", e)
-                    }
+    /// Move every bookmark tagged `from` over to `into` and drop `from`.
+    /// Returns the number of bookmarks which were tagged `from`.
+    pub fn merge_tags(&mut self, from: &str, into: &str) -> Result<usize> {
+        ensure!(from != into, "Can't merge a tag into itself");
+        let tx = self.conn.transaction()?;
+        let mut journal = Journal::begin(&tx, "tag merge", &format!("{} -> {}", from, into))?;
+
+        let from_id = tx
+            .query_row("SELECT id FROM tag WHERE name=?1", params![from], |row| row.get::<_, String>(0))
+            .optional()?
+            .with_context(|| format!("No tag named {}", from))?;
+        let bmark_ids = {
+            let mut stmt = tx.prepare("SELECT bmark_id FROM bmark_tag WHERE tag_id=?1")?;
+            let rows = stmt.query_map(params![from_id], |row| row.get::<_, String>(0))?;
+            rows.collect::<Result<Vec<_>, _>>()?
+        };
+
+        for bmark_id in &bmark_ids {
+            journal.touch(&tx, "bmark_tag", &[("bmark_id", bmark_id), ("tag_id", &from_id)])?;
+            tx.execute("DELETE FROM bmark_tag WHERE bmark_id=?1 AND tag_id=?2", params![bmark_id, from_id])?;
+            attach_tag(&tx, &mut journal, bmark_id, into)?;
+            record_revision(&tx, &mut journal, bmark_id)?;
+        }
+        journal.touch(&tx, "tag", &[("id", &from_id)])?;
+        tx.execute("DELETE FROM tag WHERE id=?1", params![from_id])?;
+
+        journal.finish(&tx)?;
+        tx.commit()?;
+        Ok(bmark_ids.len())
+    }
+
+    /// Fetch the bookmarks matching `condition` (a sql expression over `bmark b`), oldest first
+    fn query_bookmarks<P: Params>(&self, condition: &str, params: P) -> Result<Vec<Bookmark>> {
+        self.query_bookmarks_ordered(condition, SortBy::Added, params)
+    }
+
+    fn query_bookmarks_ordered<P: Params>(&self, condition: &str, sort: SortBy, params: P) -> Result<Vec<Bookmark>> {
+        let order = match sort {
+            SortBy::Added => "",
+            SortBy::Visits => "(SELECT count(*) FROM visit v WHERE v.bmark_id=b.id) DESC, ",
+            SortBy::LastVisited => "(SELECT max(v.visited_at) FROM visit v WHERE v.bmark_id=b.id) DESC NULLS LAST, ",
+        };
+        // added_at is only precise to the second, rowid keeps the ones added within it in order
+        let stmt = format!(
+            "SELECT b.id, b.url, b.name, b.description, b.category, b.added_at, b.deleted_at, t.name, \
+             b.status, b.unread_at, b.reading_at, b.read_at, b.archived_at, b.pinned, b.rating \
+             FROM bmark b LEFT JOIN bmark_tag bt ON bt.bmark_id=b.id LEFT JOIN tag t ON bt.tag_id=t.id \
+             WHERE {} ORDER BY b.pinned DESC, {}b.added_at, b.rowid, t.name",
+            condition, order
+        );
+        let mut prepared_stmt = self.conn.prepare(&stmt)?;
+        let rows = prepared_stmt.query_map(params, |row| {
+            Ok((
+                Bookmark {
+                    id: row.get(0)?,
+                    url: row.get(1)?,
+                    name: row.get(2)?,
+                    tags: vec![],
+                    desc: row.get(3)?,
+                    category: row.get(4)?,
+                    added_at: row.get(5)?,
+                    deleted_at: row.get(6)?,
+                    status: row.get::<_, Option<String>>(8)?.and_then(|s| s.parse().ok()),
+                    unread_at: row.get(9)?,
+                    reading_at: row.get(10)?,
+                    read_at: row.get(11)?,
+                    archived_at: row.get(12)?,
+                    pinned: row.get(13)?,
+                    rating: row.get(14)?,
+                    notes: vec![],
+                    alternates: vec![],
+                    reminder: None,
+                    visits: vec![],
+                    page_meta: None,
+                    revisions: vec![],
+                    link_statuses: vec![],
                 },
+                row.get::<_, Option<String>>(7)?,
+            ))
+        })?;
+
+        // the join gives one row per tag, fold them back into a single bookmark
+        let mut bookmarks: Vec<Bookmark> = vec![];
+        let mut index: HashMap<String, usize> = HashMap::new();
+        for row in rows {
+            let (bookmark, tag) = row?;
+            let idx = *index.entry(bookmark.id.clone()).or_insert_with(|| {
+                bookmarks.push(bookmark);
+                bookmarks.len() - 1
             });
-            if i + 1 < range {
-                res.push('|');
+            if let Some(tag) = tag {
+                bookmarks[idx].tags.push(tag);
+            }
+        }
+
+        let ids = serde_json::to_string(&bookmarks.iter().map(|b| &b.id).collect::<Vec<_>>())?;
+        let mut stmt = self.conn.prepare(
+            "SELECT bmark_id, seq, body, created_at, updated_at FROM note \
+             WHERE bmark_id IN (SELECT value FROM json_each(?1)) ORDER BY bmark_id, seq",
+        )?;
+        let notes = stmt.query_map(params![ids], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                Note { seq: row.get(1)?, body: row.get(2)?, created_at: row.get(3)?, updated_at: row.get(4)? },
+            ))
+        })?;
+        for note in notes {
+            let (bmark_id, note) = note?;
+            bookmarks[index[&bmark_id]].notes.push(note);
+        }
+
+        let mut stmt = self.conn.prepare(
+            "SELECT bmark_id, url, source, captured_at, added_at FROM alternate \
+             WHERE bmark_id IN (SELECT value FROM json_each(?1)) ORDER BY added_at DESC, rowid DESC",
+        )?;
+        let alternates = stmt.query_map(params![ids], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                Alternate { url: row.get(1)?, source: row.get(2)?, captured_at: row.get(3)?, added_at: row.get(4)? },
+            ))
+        })?;
+        for alternate in alternates {
+            let (bmark_id, alternate) = alternate?;
+            bookmarks[index[&bmark_id]].alternates.push(alternate);
+        }
+
+        for reminder in self.reminders("bmark_id IN (SELECT value FROM json_each(?1))", params![ids])? {
+            let idx = index[&reminder.bmark_id];
+            bookmarks[idx].reminder = Some(reminder);
+        }
+
+        let mut stmt = self.conn.prepare(
+            "SELECT bmark_id, visited_at FROM visit WHERE bmark_id IN (SELECT value FROM json_each(?1)) ORDER BY visited_at, id",
+        )?;
+        let visits = stmt.query_map(params![ids], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+        for visit in visits {
+            let (bmark_id, visited_at) = visit?;
+            bookmarks[index[&bmark_id]].visits.push(visited_at);
+        }
+
+        let mut stmt = self.conn.prepare(
+            "SELECT bmark_id, title, description, canonical_url, lang, fetched_at FROM page_meta \
+             WHERE bmark_id IN (SELECT value FROM json_each(?1))",
+        )?;
+        let metas = stmt.query_map(params![ids], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                PageMeta { title: row.get(1)?, description: row.get(2)?, canonical_url: row.get(3)?, lang: row.get(4)?, fetched_at: row.get(5)? },
+            ))
+        })?;
+        for meta in metas {
+            let (bmark_id, meta) = meta?;
+            bookmarks[index[&bmark_id]].page_meta = Some(meta);
+        }
+
+        for (bmark_id, revision) in self.revisions_where("bmark_id IN (SELECT value FROM json_each(?1))", params![ids])? {
+            bookmarks[index[&bmark_id]].revisions.push(revision);
+        }
+
+        for status in self.link_statuses_where("bmark_id IN (SELECT value FROM json_each(?1))", params![ids])? {
+            let idx = index[&status.bmark_id];
+            bookmarks[idx].link_statuses.push(status);
+        }
+
+        Ok(bookmarks)
+    }
+
+    /// All the bookmarks which are not in trash
+    pub fn bookmarks(&self) -> Result<Vec<Bookmark>> {
+        self.query_bookmarks("b.deleted_at IS NULL", [])
+    }
+
+    /// Every bookmark, trashed ones included
+    pub fn all_bookmarks(&self) -> Result<Vec<Bookmark>> {
+        self.query_bookmarks("1", [])
+    }
+
+    /// The bookmarks not in trash, picked and ordered as `options` asks
+    pub fn query(&self, options: &ListOptions) -> Result<Vec<Bookmark>> {
+        let mut condition = String::from("b.deleted_at IS NULL");
+        let mut params: Vec<&str> = vec![];
+        if !options.tags.is_empty() {
+            let placeholders = vec!["?"; options.tags.len()].join(", ");
+            let tagged = format!("SELECT bt.bmark_id FROM bmark_tag bt JOIN tag t ON t.id=bt.tag_id WHERE t.name IN ({})", placeholders);
+            match options.tag_mode {
+                TagMode::Any => condition.push_str(&format!(" AND b.id IN ({})", tagged)),
+                TagMode::All => condition.push_str(&format!(
+                    " AND b.id IN ({} GROUP BY bt.bmark_id HAVING count(DISTINCT t.name)={})",
+                    tagged,
+                    options.tags.iter().collect::<HashSet<_>>().len()
+                )),
             }
+            params.extend(options.tags.iter().map(String::as_str));
+        }
+        if let Some(category) = &options.category {
+            condition.push_str(" AND (b.category=? OR substr(b.category, 1, length(?)+1)=?||'/')");
+            params.extend([category.as_str(); 3]);
+        }
+        let link_condition = match options.link {
+            Some(LinkFilter::Broken) => Some("(ls.error IS NOT NULL OR ls.status IS NULL OR ls.status >= 400)"),
+            Some(LinkFilter::Redirected) => Some("ls.final_url != ls.url"),
+            None => None,
+        };
+        if let Some(link_condition) = link_condition {
+            condition.push_str(&format!(" AND {}", LATEST_LINK_STATUS.replace("{}", link_condition)));
+        }
+        if let Some(status) = options.status {
+            condition.push_str(" AND b.status=?");
+            params.push(status.as_str());
         }
+        if options.pinned {
+            condition.push_str(" AND b.pinned");
+        }
+        let min_rating = options.min_rating.map(|r| r.to_string());
+        if let Some(min_rating) = &min_rating {
+            condition.push_str(" AND b.rating >= CAST(? AS INTEGER)");
+            params.push(min_rating);
+        }
+        self.query_bookmarks_ordered(&condition, options.sort, rusqlite::params_from_iter(params))
+    }
 
-        return Ok(res);
+    /// Bookmarks not in trash whose URL, name, description, category, one of the tags or one of
+    /// the notes contains `query`, ignoring case
+    pub fn search(&self, query: &str) -> Result<Vec<Bookmark>> {
+        let condition = "b.deleted_at IS NULL AND (instr(lower(b.url), lower(?1)) OR instr(lower(b.name), lower(?1)) \
+            OR instr(lower(b.description), lower(?1)) OR instr(lower(b.category), lower(?1)) \
+            OR b.id IN (SELECT bt.bmark_id FROM bmark_tag bt JOIN tag t ON t.id=bt.tag_id WHERE instr(lower(t.name), lower(?1))) \
+            OR b.id IN (SELECT n.bmark_id FROM note n WHERE instr(lower(n.body), lower(?1))))";
+        self.query_bookmarks(condition, params![query])
+    }
+
+    /// Make `text` what the bookmark is found by in `search_content` for `source`, which tells
+    /// where the text came from (`page` for the bookmarked page itself), replacing what was
+    /// indexed for it before
+    pub fn index_text(&self, id: &str, source: &str, text: &str) -> Result<()> {
+        index_text(&self.conn, id, source, text)
+    }
+
+    /// Bookmarks not in trash whose indexed text has every word of `query`, best matches first,
+    /// each with a snippet of the text around the words, which are put in [brackets]
+    pub fn search_content(&self, query: &str) -> Result<Vec<(Bookmark, String)>> {
+        // quoted, words are taken as they are rather than as fts5 query syntax
+        let words = query
+            .split_whitespace()
+            .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+            .collect::<Vec<_>>();
+        if words.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut stmt = self.conn.prepare(
+            "SELECT f.bmark_id, snippet(content_fts, 2, '[', ']', '…', 12) FROM content_fts f \
+             JOIN bmark b ON b.id=f.bmark_id WHERE content_fts MATCH ?1 AND b.deleted_at IS NULL ORDER BY rank",
+        )?;
+        let rows = stmt.query_map(params![words.join(" ")], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+        let mut results: Vec<(Bookmark, String)> = vec![];
+        for row in rows {
+            let (id, snippet) = row?;
+            // a bookmark shows up once, with its best matching text
+            if results.iter().all(|(b, _)| b.id != id) {
+                results.push((self.bookmark(&id)?, snippet));
+            }
+        }
+        Ok(results)
+    }
+
+    /// Find the id of the bookmark referred to by `query`: a full id, a unique id prefix (at
+    /// least 4 characters, the way git takes short hashes) or the bookmark's URL.
+    /// Bookmarks in trash are left out, see `resolve_trashed` for those.
+    pub fn resolve(&self, query: &str) -> Result<String> {
+        if let Some(id) = self.resolve_in(query, "deleted_at IS NULL")? {
+            return Ok(id);
+        }
+        if self.resolve_in(query, "deleted_at IS NOT NULL")?.is_some() {
+            bail!("{} is in trash, bring it back first with: bmark trash restore {}", query, query);
+        }
+        bail!("No bookmark matches {}", query)
+    }
+
+    /// Like `resolve`, but among the bookmarks in trash
+    pub fn resolve_trashed(&self, query: &str) -> Result<String> {
+        self.resolve_in(query, "deleted_at IS NOT NULL")?
+            .with_context(|| format!("No bookmark in trash matches {}", query))
+    }
+
+    /// The bookmark `query` refers to among the ones matching `scope`, None if none does
+    fn resolve_in(&self, query: &str, scope: &str) -> Result<Option<String>> {
+        let mut candidates = self.query_ids(&format!("id=?1 AND {}", scope), query)?;
+        if candidates.is_empty() {
+            candidates = self.query_ids(&format!("url=?1 AND {}", scope), query)?;
+        }
+        if candidates.is_empty() && query.len() >= 4 {
+            candidates = self.query_ids(&format!("substr(id, 1, length(?1))=?1 AND {}", scope), query)?;
+        }
+        match candidates.len() {
+            0 => Ok(None),
+            1 => Ok(Some(candidates.remove(0))),
+            _ => bail!("{} is ambiguous, it matches:\n  {}", query, candidates.join("\n  ")),
+        }
+    }
+
+    fn query_ids(&self, condition: &str, query: &str) -> Result<Vec<String>> {
+        let mut stmt = self
+            .conn
+            .prepare(&format!("SELECT id FROM bmark WHERE {} ORDER BY id", condition))?;
+        let rows = stmt.query_map(params![query], |row| row.get(0))?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// The bookmark with the given id, whether in trash or not
+    pub fn bookmark(&self, id: &str) -> Result<Bookmark> {
+        self.query_bookmarks("b.id=?1", params![id])?
+            .pop()
+            .with_context(|| format!("No bookmark with id {}", id))
     }
 
     // NOTE: when I'll make change in the list in cli.rs, this logic will be changed and instead of
     // having struct for combinations which I think is useful, something generic will be needed
-    pub fn list(&self, output_type: OutputType, column: ListColumn) -> Result<()> {
-        match output_type {
-            OutputType::All => {
-                let mut stmt = String::from("SELECT ");
-                let join_stmt = "FROM bmark b LEFT JOIN bmark_tag bt ON bt.bmark_id=b.id LEFT JOIN tag t ON bt.tag_id=t.id";
-                let col_count: usize;
-                match column {
-                    ListColumn::All => {
-                        stmt.push_str("b.id, b.url, b.name, t.name, b.description, b.category ");
-                        stmt.push_str(join_stmt);
-                        let mut prepared_stmt = self.conn.prepare(&stmt)?;
-                        col_count = 5;
-                        let mut bmark_map: HashMap<String, BookmarkAll> = HashMap::new();
-                        let rows = prepared_stmt.query_map([], |row| {
-                            let mut bid: String = String::new();
-                            let mut url: String = String::new();
-                            let mut name: String = String::new();
-                            let mut desc: Option<String> = None;
-                            let mut category: Option<String> = None;
-                            let mut tag: Vec<String> = vec![];
-                            for i in 0..col_count {
-                                match &row.get::<_, String>(i) {
-                                    Ok(s) => {
-                                        if i == 0 {
-                                            bid = s.to_owned();
-                                        } else if i == 1 {
-                                            url = s.to_owned();
-                                        } else if i == 2 {
-                                            name = s.to_owned();
-                                        } else if i == 3 {
-                                            tag.push(s.to_owned());
-                                        } else if i == 4 {
-                                            desc = Some(s.to_owned());
-                                        } else if i == 5 {
-                                            category = Some(s.to_owned());
-                                        }
-                                    },
-                                    Err(_) => {},
-                                }
-                            }
-                            let bookmark = BookmarkAll {
-                                url,
-                                name: Some(name),
-                                tag,
-                                desc,
-                                category,
-                            };
-
-                            Ok((bid, bookmark))
-                        })?;
-
-                        for row in rows {
-                            if let Ok(mut row) = row {
-                                match bmark_map.get_mut(&row.0) {
-                                    Some(ref mut b) => {
-                                        b.tag.append(&mut row.1.tag);
-                                    },
-                                    None => {
-                                        bmark_map.insert(row.0, row.1);
-                                    }
-                                }
-                            }
-                        }
-
-                        for bmark in bmark_map.values() {
-                            println!("{:?}", bmark);
-                        }
-                    },
-                    // NOTE: Has problem of duplicacy. Will fix on second iteration of listing
-                    ListColumn::Url => {
-                        stmt.push_str("b.url ");
-                        stmt.push_str(join_stmt);
-                        let mut prepared_stmt = self.conn.prepare(&stmt)?;
-                        let rows = prepared_stmt.query_map([], |row| {
-                            Ok(BookmarkUrl {
-                                url: row.get::<_, String>(0)?,
-                            })
-                        })?;
-
-                        for row in rows {
-                            if let Ok(row) = row {
-                                println!("{:?}", row);
-                            }
-                        }
-                    }
-                    // NOTE: Has problem of duplicacy. Will fix on second iteration of listing
-                    ListColumn::Desc => {
-                        stmt.push_str("b.url, b.description ");
-                        col_count = 2;
-                        stmt.push_str(join_stmt);
-                        let mut prepared_stmt = self.conn.prepare(&stmt)?;
-                        let rows = prepared_stmt.query_map([], |row| {
-                            let mut url = String::new();
-                            let mut desc = None;
-                            for i in 0..col_count {
-                                let s = row.get::<_, String>(i).ok();
-                                if i == 0 {
-                                    url = s.expect("Error retrieving URL from db");
-                                } else if i == 1 { 
-                                    desc = s;
-                                }
-                            }
-                            Ok(BookmarkDesc {
-                                url,
-                                desc,
-                            })
-                        })?;
-
-                        for row in rows {
-                            if let Ok(row) = row {
-                                println!("{:?}", row);
-                            }
-                        }
-                    }
-                    ListColumn::Tag => {
-                        stmt.push_str("b.id, b.url, t.name ");
-                        col_count = 3;
-                        stmt.push_str(join_stmt);
-                        let mut prepared_stmt = self.conn.prepare(&stmt)?;
-                        let rows = prepared_stmt.query_map([], |row| {
-                            let mut bid = String::new();
-                            let mut url = String::new();
-                            let mut tag = vec![];
-                            for i in 0..col_count {
-                                if i == 0 {
-                                    bid = row.get::<_, String>(i).ok().expect("Error in retrieving bookmark id");
-                                } else if i == 1 {
-                                    url = row.get::<_, String>(i).ok().expect("Error in getting bookmarked url");
-                                } else {
-                                    tag = row.get::<_, String>(i).ok().map_or(vec![], |s| vec![s]);
-                                }
-                            }
-                            Ok((bid, BookmarkTag {
-                                url,
-                                tag
-                            }))
-                        })?;
-                        let mut bmark_map: HashMap<String, BookmarkTag> = HashMap::new();
-                        for row in rows {
-                            if let Ok(mut row) = row {
-                                if let Some(val) = bmark_map.get_mut(&row.0) {
-                                    val.tag.append(&mut row.1.tag);
-                                } else {
-                                    bmark_map.insert(row.0, row.1);
-                                }
-                            }
-                        }
-
-                        for val in bmark_map.values() {
-                            println!("{:?}", val);
-                        }
-                    }
-                }
-                // stmt.push_str("FROM bmark b LEFT JOIN bmark_tag bt ON bt.bmark_id=b.id LEFT JOIN tag t ON bt.tag_id=t.id");
-                // let mut prepared_stmt = self.conn.prepare(&stmt)?;
-                // // why is this limiting row count to 4 ?
-                // let rows = prepared_stmt.query_map([], |row| Self::make_row(row, col_count))?;
-                //
-                // for row in rows {
-                //     if let Ok(row) = row {
-                //         println!("{}", row);
-                //     }
-                // }
-            },
-            OutputType::Tag(_) => {},
-        }
-        _ = output_type;
-        _ = column;
+    pub fn list(&self, output_type: OutputType, column: ListColumn, options: &ListOptions) -> Result<()> {
+        let bookmarks = match output_type {
+            OutputType::All => self.query(options)?,
+            OutputType::Tag(tags) => self.query(&ListOptions { tags, ..options.clone() })?,
+        };
+        for bmark in bookmarks {
+            match column {
+                ListColumn::All => println!("{:?}", BookmarkAll {
+                    url: bmark.url,
+                    name: bmark.name,
+                    tag: bmark.tags,
+                    desc: bmark.desc,
+                    category: bmark.category,
+                }),
+                ListColumn::Url => println!("{:?}", BookmarkUrl { url: bmark.url }),
+                ListColumn::Desc => println!("{:?}", BookmarkDesc {
+                    url: bmark.url,
+                    desc: bmark.desc,
+                }),
+                ListColumn::Tag => println!("{:?}", BookmarkTag {
+                    url: bmark.url,
+                    tag: bmark.tags,
+                }),
+            }
+        }
+
+        Ok(())
+    }
 
+    /// Keep a result of `check`, earlier results stay as the link's history
+    pub fn record_link_status(&self, s: &LinkStatus) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO link_status (bmark_id, url, status, final_url, permanent, error, checked_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![s.bmark_id, s.url, s.status, s.final_url, s.permanent, s.error, s.checked_at],
+        )?;
         Ok(())
     }
+
+    /// Every check of the bookmark's link, newest first
+    pub fn link_statuses(&self, id: &str) -> Result<Vec<LinkStatus>> {
+        self.link_statuses_where("bmark_id=?1", params![id])
+    }
+
+    fn link_statuses_where<P: Params>(&self, condition: &str, params: P) -> Result<Vec<LinkStatus>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT bmark_id, url, status, final_url, permanent, error, checked_at \
+             FROM link_status WHERE {} ORDER BY id DESC",
+            condition
+        ))?;
+        let rows = stmt.query_map(params, |row| {
+            Ok(LinkStatus {
+                bmark_id: row.get(0)?,
+                url: row.get(1)?,
+                status: row.get(2)?,
+                final_url: row.get(3)?,
+                permanent: row.get(4)?,
+                error: row.get(5)?,
+                checked_at: row.get(6)?,
+            })
+        })?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Point the bookmarks whose last check found a permanent redirect at where it leads, as
+    /// one operation. Bookmarks whose URL changed since are left alone. Returns the
+    /// `(id, old url, new url)` of each changed bookmark.
+    pub fn fix_redirects(&mut self) -> Result<Vec<(String, String, String)>> {
+        let stmt = "SELECT b.id, b.url, ls.final_url FROM bmark b \
+                    JOIN link_status ls ON ls.id=(SELECT max(id) FROM link_status WHERE bmark_id=b.id) \
+                    WHERE b.deleted_at IS NULL AND ls.url=b.url AND ls.permanent AND ls.final_url != ls.url AND ls.error IS NULL AND ls.status < 400 \
+                    ORDER BY b.added_at, b.id";
+        let moved = self
+            .conn
+            .prepare(stmt)?
+            .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let tx = self.conn.transaction()?;
+        let mut journal = Journal::begin(&tx, "fix redirects", &format!("{} bookmark(s)", moved.len()))?;
+        for (id, _, new_url) in &moved {
+            let edit = BookmarkEdit { url: Some(new_url.clone()), ..Default::default() };
+            apply_edit(&tx, &mut journal, id, &edit)?;
+        }
+        journal.finish(&tx)?;
+        tx.commit()?;
+        Ok(moved)
+    }
+
+    /// Directory next to the db holding the archived pages
+    pub fn archive_dir(&self) -> Result<PathBuf> {
+        let db = self.conn.path().filter(|p| !p.is_empty()).context("An in-memory db has no archive directory")?;
+        Ok(Path::new(db).parent().unwrap_or(Path::new(".")).join("archive"))
+    }
+
+    /// Remember a snapshot of the bookmark's page taken just now, returning it as recorded
+    pub fn record_snapshot(&self, id: &str, snapshot: &Snapshot) -> Result<Snapshot> {
+        self.conn.execute(
+            "INSERT INTO archive (bmark_id, url, hash, path, size, assets) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![id, snapshot.url, snapshot.hash, snapshot.path, snapshot.size, snapshot.assets],
+        )?;
+        let row_id = self.conn.last_insert_rowid();
+        self.snapshots(id)?.into_iter().find(|s| s.id == row_id).context("Snapshot wasn't recorded")
+    }
+
+    /// Delete the archived files which no snapshot uses anymore, like the ones of bookmarks
+    /// purged from trash
+    pub fn archive_gc(&self) -> Result<GcReport> {
+        let pages = self
+            .conn
+            .prepare("SELECT DISTINCT path FROM archive")?
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        archive::gc(&self.archive_dir()?, pages.iter().map(String::as_str))
+    }
+
+    /// Snapshots of the bookmark's page, newest first
+    pub fn snapshots(&self, id: &str) -> Result<Vec<Snapshot>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, bmark_id, url, hash, path, size, assets, archived_at FROM archive WHERE bmark_id=?1 ORDER BY id DESC",
+        )?;
+        let rows = stmt.query_map(params![id], |row| {
+            Ok(Snapshot {
+                id: row.get(0)?,
+                bmark_id: row.get(1)?,
+                url: row.get(2)?,
+                hash: row.get(3)?,
+                path: row.get(4)?,
+                size: row.get(5)?,
+                assets: row.get(6)?,
+                archived_at: row.get(7)?,
+            })
+        })?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Ask `provider` for its copy of the bookmark's page captured closest to when it was
+    /// bookmarked, and keep it as an alternate link. Returns none when the archive has no copy.
+    pub fn rescue(&mut self, id: &str, provider: &dyn ArchiveProvider) -> Result<Option<ArchivedCopy>> {
+        let bookmark = self.bookmark(id)?;
+        let Some(copy) = provider.closest(&bookmark.url, &date::parse_date(&bookmark.added_at)?)? else {
+            return Ok(None);
+        };
+        let tx = self.conn.transaction()?;
+        let mut journal = Journal::begin(&tx, "rescue", id)?;
+        journal.touch(&tx, "alternate", &[("bmark_id", id), ("url", &copy.url)])?;
+        tx.execute(
+            "INSERT OR REPLACE INTO alternate (bmark_id, url, source, captured_at) VALUES (?1, ?2, ?3, ?4)",
+            params![id, copy.url, provider.name(), copy.captured_at],
+        )?;
+        journal.finish(&tx)?;
+        tx.commit()?;
+        Ok(Some(copy))
+    }
+
+    /// Alternate links of the bookmark, newest first
+    pub fn alternates(&self, id: &str) -> Result<Vec<Alternate>> {
+        Ok(self.bookmark(id)?.alternates)
+    }
+
+    /// Icon of the site `url` is on, with its type, none when the site has none. Icons are
+    /// cached per domain, the site is only asked again once the cached answer is older than
+    /// `options.max_age`.
+    pub fn favicon(&self, url: &str, options: &FaviconOptions) -> Result<Option<Icon>> {
+        let domain = fetch::host(url).to_lowercase();
+        let cached = self
+            .conn
+            .query_row(
+                "SELECT mime, data FROM favicon WHERE domain=?1 AND fetched_at > datetime('now', ?2)",
+                params![domain, format!("-{} seconds", options.max_age.as_secs())],
+                |row| Ok((row.get::<_, Option<String>>(0)?, row.get::<_, Option<Vec<u8>>>(1)?)),
+            )
+            .optional()?;
+        // a cached type which isn't a known image type isn't trusted, the site is asked again
+        if let Some((mime, bytes)) = cached.filter(|(mime, _)| mime.as_deref().is_none_or(favicon::is_image_type)) {
+            return Ok(mime.zip(bytes).map(|(mime, bytes)| Icon { bytes, mime }));
+        }
+        let icon = favicon::resolve(url, &options.fetch)?;
+        self.conn.execute(
+            "INSERT OR REPLACE INTO favicon (domain, mime, data) VALUES (?1, ?2, ?3)",
+            params![domain, icon.as_ref().map(|i| &i.mime), icon.as_ref().map(|i| &i.bytes)],
+        )?;
+        Ok(icon)
+    }
+
+    /// Remember that the bookmark was opened just now
+    pub fn record_visit(&self, id: &str) -> Result<()> {
+        self.conn.execute("INSERT INTO visit (bmark_id) VALUES (?1)", params![id])?;
+        Ok(())
+    }
+
+    /// Move the bookmark to trash, it can be brought back with `restore` until trash is emptied
+    pub fn trash(&mut self, id: &str) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        let mut journal = Journal::begin(&tx, "rm", id)?;
+        journal.touch(&tx, "bmark", &[("id", id)])?;
+        let changed = tx.execute(
+            "UPDATE bmark SET deleted_at=current_timestamp WHERE id=?1 AND deleted_at IS NULL",
+            params![id],
+        )?;
+        ensure!(changed == 1, "No bookmark with id {}", id);
+        journal.finish(&tx)?;
+        Ok(tx.commit()?)
+    }
+
+    /// Move the bookmark to `status` in the reading queue, noting when it happened
+    pub fn set_status(&mut self, id: &str, status: ReadStatus) -> Result<()> {
+        let kind = format!("mark {}", status.as_str());
+        let columns = format!("status=?2, {}=current_timestamp", status.column());
+        self.update_bookmark(id, &kind, &columns, params![id, status.as_str()])
+    }
+
+    /// Write a note on the bookmark, returning its number
+    pub fn add_note(&mut self, id: &str, body: &str) -> Result<i64> {
+        ensure!(!body.trim().is_empty(), "The note is empty");
+        let tx = self.conn.transaction()?;
+        let mut journal = Journal::begin(&tx, "note add", id)?;
+        let seq: i64 = tx.query_row("SELECT coalesce(max(seq), 0) + 1 FROM note WHERE bmark_id=?1", params![id], |row| row.get(0))?;
+        journal.touch(&tx, "note", &[("bmark_id", id), ("seq", &seq.to_string())])?;
+        tx.execute("INSERT INTO note (bmark_id, seq, body) VALUES (?1, ?2, ?3)", params![id, seq, body])?;
+        index_text(&tx, id, &format!("note:{}", seq), body)?;
+        journal.finish(&tx)?;
+        tx.commit()?;
+        Ok(seq)
+    }
+
+    /// Replace the text of the bookmark's note `seq`
+    pub fn edit_note(&mut self, id: &str, seq: i64, body: &str) -> Result<()> {
+        ensure!(!body.trim().is_empty(), "The note is empty");
+        let tx = self.conn.transaction()?;
+        let mut journal = Journal::begin(&tx, "note edit", id)?;
+        journal.touch(&tx, "note", &[("bmark_id", id), ("seq", &seq.to_string())])?;
+        let changed = tx.execute(
+            "UPDATE note SET body=?3, updated_at=current_timestamp WHERE bmark_id=?1 AND seq=?2",
+            params![id, seq, body],
+        )?;
+        ensure!(changed == 1, "No note #{} on bookmark {}", seq, id);
+        index_text(&tx, id, &format!("note:{}", seq), body)?;
+        journal.finish(&tx)?;
+        Ok(tx.commit()?)
+    }
+
+    /// The bookmark's notes, oldest first
+    pub fn notes(&self, id: &str) -> Result<Vec<Note>> {
+        Ok(self.bookmark(id)?.notes)
+    }
+
+    /// Remind about the bookmark at `due_at`, replacing the reminder it had
+    pub fn remind(&mut self, id: &str, due_at: &Datetime) -> Result<()> {
+        self.bookmark(id)?;
+        let tx = self.conn.transaction()?;
+        let mut journal = Journal::begin(&tx, "remind", id)?;
+        journal.touch(&tx, "reminder", &[("bmark_id", id)])?;
+        tx.execute(
+            "INSERT OR REPLACE INTO reminder (bmark_id, due_at) VALUES (?1, ?2)",
+            params![id, due_at.to_string()],
+        )?;
+        journal.finish(&tx)?;
+        Ok(tx.commit()?)
+    }
+
+    /// Put the bookmark's reminder off until `due_at`
+    pub fn snooze(&mut self, id: &str, due_at: &Datetime) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        let mut journal = Journal::begin(&tx, "snooze", id)?;
+        journal.touch(&tx, "reminder", &[("bmark_id", id)])?;
+        let changed = tx.execute(
+            "UPDATE reminder SET due_at=?2, snoozed=snoozed+1 WHERE bmark_id=?1",
+            params![id, due_at.to_string()],
+        )?;
+        ensure!(changed == 1, "No reminder on bookmark {}", id);
+        journal.finish(&tx)?;
+        Ok(tx.commit()?)
+    }
+
+    /// Drop the bookmark's reminder
+    pub fn dismiss(&mut self, id: &str) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        let mut journal = Journal::begin(&tx, "dismiss", id)?;
+        journal.touch(&tx, "reminder", &[("bmark_id", id)])?;
+        let changed = tx.execute("DELETE FROM reminder WHERE bmark_id=?1", params![id])?;
+        ensure!(changed == 1, "No reminder on bookmark {}", id);
+        journal.finish(&tx)?;
+        Ok(tx.commit()?)
+    }
+
+    /// The bookmark's reminder, if it has one
+    pub fn reminder(&self, id: &str) -> Result<Option<Reminder>> {
+        Ok(self.reminders("bmark_id=?1", params![id])?.pop())
+    }
+
+    /// Reminders due by `at`, on bookmarks not in trash, the ones due the longest first
+    pub fn due(&self, at: &Datetime) -> Result<Vec<(Bookmark, Reminder)>> {
+        let reminders = self.reminders(
+            "due_at <= ?1 AND bmark_id IN (SELECT id FROM bmark WHERE deleted_at IS NULL)",
+            params![at.to_string()],
+        )?;
+        reminders.into_iter().map(|r| Ok((self.bookmark(&r.bmark_id)?, r))).collect()
+    }
+
+    fn reminders<P: Params>(&self, condition: &str, params: P) -> Result<Vec<Reminder>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT bmark_id, due_at, created_at, snoozed FROM reminder WHERE {} ORDER BY due_at, bmark_id",
+            condition
+        ))?;
+        let rows = stmt.query_map(params, |row| {
+            Ok(Reminder { bmark_id: row.get(0)?, due_at: row.get(1)?, created_at: row.get(2)?, snoozed: row.get(3)? })
+        })?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Pin the bookmark, or unpin it, so it's listed first
+    pub fn pin(&mut self, id: &str, pinned: bool) -> Result<()> {
+        self.update_bookmark(id, if pinned { "pin" } else { "unpin" }, "pinned=?2", params![id, pinned])
+    }
+
+    /// Rate the bookmark 1 to 5 stars, none clears the rating
+    pub fn rate(&mut self, id: &str, rating: Option<u8>) -> Result<()> {
+        ensure!(rating.is_none_or(|r| (1..=5).contains(&r)), "Ratings go from 1 to 5 stars");
+        self.update_bookmark(id, "rate", "rating=?2", params![id, rating])
+    }
+
+    /// Set `columns` of the bookmark as a single operation of `kind`, `?1` being its id
+    fn update_bookmark<P: Params>(&mut self, id: &str, kind: &str, columns: &str, params: P) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        let mut journal = Journal::begin(&tx, kind, id)?;
+        journal.touch(&tx, "bmark", &[("id", id)])?;
+        let changed = tx.execute(&format!("UPDATE bmark SET {} WHERE id=?1 AND deleted_at IS NULL", columns), params)?;
+        ensure!(changed == 1, "No bookmark with id {}", id);
+        journal.finish(&tx)?;
+        Ok(tx.commit()?)
+    }
+
+    /// The unread bookmark which has been waiting the longest, or a random one
+    pub fn next_unread(&self, random: bool) -> Result<Option<Bookmark>> {
+        let order = if random { "random()" } else { "unread_at, added_at, id" };
+        let id = self
+            .conn
+            .query_row(
+                &format!("SELECT id FROM bmark WHERE status='unread' AND deleted_at IS NULL ORDER BY {} LIMIT 1", order),
+                [],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?;
+        id.map(|id| self.bookmark(&id)).transpose()
+    }
+
+    /// Bookmarks currently in trash, most recently deleted first
+    pub fn trashed(&self) -> Result<Vec<Bookmark>> {
+        let mut bookmarks = self.query_bookmarks("b.deleted_at IS NOT NULL", [])?;
+        bookmarks.sort_by(|a, b| b.deleted_at.cmp(&a.deleted_at));
+        Ok(bookmarks)
+    }
+
+    pub fn restore(&mut self, id: &str) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        let mut journal = Journal::begin(&tx, "restore", id)?;
+        journal.touch(&tx, "bmark", &[("id", id)])?;
+        let changed = tx.execute(
+            "UPDATE bmark SET deleted_at=NULL WHERE id=?1 AND deleted_at IS NOT NULL",
+            params![id],
+        )?;
+        ensure!(changed == 1, "No bookmark with id {} in trash", id);
+        journal.finish(&tx)?;
+        Ok(tx.commit()?)
+    }
+
+    /// Permanently delete the trashed bookmarks, only the ones trashed at least `older_than`
+    /// seconds ago if given. Returns the number of bookmarks purged.
+    pub fn empty_trash(&mut self, older_than: Option<u64>) -> Result<usize> {
+        let cutoff = format!("-{} seconds", older_than.unwrap_or(0));
+        let condition = "deleted_at IS NOT NULL AND deleted_at <= datetime('now', ?1)";
+
+        let tx = self.conn.transaction()?;
+        let mut journal = Journal::begin(&tx, "trash empty", &cutoff)?;
+        let child_condition = format!("bmark_id IN (SELECT id FROM bmark WHERE {})", condition);
+        for table in ["bmark_tag", "revision", "visit", "page_meta", "link_status", "archive", "alternate", "note", "reminder"] {
+            journal.touch_where(&tx, table, &child_condition, params![cutoff])?;
+            tx.execute(&format!("DELETE FROM {} WHERE {}", table, child_condition), params![cutoff])?;
+        }
+        // the text index is derived from pages which can be fetched again, so it isn't journaled
+        tx.execute(&format!("DELETE FROM content_fts WHERE {}", child_condition), params![cutoff])?;
+        journal.touch_where(&tx, "bmark", condition, params![cutoff])?;
+        let purged = tx.execute(&format!("DELETE FROM bmark WHERE {}", condition), params![cutoff])?;
+        journal.finish(&tx)?;
+        tx.commit()?;
+
+        Ok(purged)
+    }
+
+    /// Revert the last `count` operations, newest first, each one as a unit.
+    /// Returns the operations which got undone.
+    pub fn undo(&mut self, count: usize) -> Result<Vec<Operation>> {
+        let mut undone = vec![];
+        for _ in 0..count {
+            match journal::undo_last(&mut self.conn)? {
+                Some(operation) => undone.push(operation),
+                None => break,
+            }
+        }
+        // notes are indexed as they're written, undoing may have brought back other ones
+        if !undone.is_empty() {
+            self.conn.execute("DELETE FROM content_fts WHERE source LIKE 'note:%'", [])?;
+            self.conn.execute(
+                "INSERT INTO content_fts (bmark_id, source, body) SELECT bmark_id, 'note:' || seq, body FROM note",
+                [],
+            )?;
+        }
+        Ok(undone)
+    }
+
+    /// The last `limit` recorded operations, newest first
+    pub fn history(&self, limit: usize) -> Result<Vec<Operation>> {
+        journal::operations(&self.conn, "1", limit)
+    }
+}
+
+/// State of a bookmark's fields as recorded after an insert or update
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Revision {
+    pub rev: i64,
+    pub url: String,
+    pub name: Option<String>,
+    pub desc: Option<String>,
+    pub category: Option<String>,
+    pub tags: Vec<String>,
+    pub created_at: String,
+}
+
+/// A bookmark yet to be added, either from `add` or read by an importer
+#[derive(Debug, Default, Clone)]
+pub struct NewBookmark {
+    /// Id to keep, when restoring a dump. A new one is made if not given.
+    pub id: Option<String>,
+    pub url: String,
+    pub name: Option<String>,
+    pub tags: Vec<String>,
+    pub desc: Option<String>,
+    pub category: Option<String>,
+    /// `yyyy-mm-dd hh:mm:ss` in UTC, now if not given
+    pub added_at: Option<String>,
+    pub deleted_at: Option<String>,
+    /// When given without the time it was set, the bookmark got it when it was added
+    pub status: Option<ReadStatus>,
+    pub unread_at: Option<String>,
+    pub reading_at: Option<String>,
+    pub read_at: Option<String>,
+    pub archived_at: Option<String>,
+    pub pinned: bool,
+    pub rating: Option<u8>,
+    pub notes: Vec<Note>,
+    pub alternates: Vec<Alternate>,
+    pub reminder: Option<Reminder>,
+    pub visits: Vec<String>,
+    pub page_meta: Option<PageMeta>,
+    /// Restored as they are, the current state is recorded as a new one only if it differs
+    /// from the last of them
+    pub revisions: Vec<Revision>,
+    /// Newest first
+    pub link_statuses: Vec<LinkStatus>,
+}
+
+impl From<Bookmark> for NewBookmark {
+    fn from(b: Bookmark) -> Self {
+        NewBookmark {
+            id: Some(b.id).filter(|id| !id.is_empty()),
+            url: b.url,
+            name: b.name,
+            tags: b.tags,
+            desc: b.desc,
+            category: b.category,
+            added_at: Some(b.added_at).filter(|at| !at.is_empty()),
+            deleted_at: b.deleted_at,
+            status: b.status,
+            unread_at: b.unread_at,
+            reading_at: b.reading_at,
+            read_at: b.read_at,
+            archived_at: b.archived_at,
+            pinned: b.pinned,
+            rating: b.rating,
+            notes: b.notes,
+            alternates: b.alternates,
+            reminder: b.reminder,
+            visits: b.visits,
+            page_meta: b.page_meta,
+            revisions: b.revisions,
+            link_statuses: b.link_statuses,
+        }
+    }
+}
+
+/// Changes to apply on a bookmark with `BMark::edit`, `None` leaves the field as it is
+#[derive(Debug, Default)]
+pub struct BookmarkEdit {
+    pub url: Option<String>,
+    pub name: Option<String>,
+    pub desc: Option<String>,
+    pub category: Option<String>,
+    pub add_tags: Vec<String>,
+    pub remove_tags: Vec<String>,
+}
+
+fn new_uuid() -> String {
+    let epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Duration befor Unix Epoch");
+    let ts = Timestamp::from_unix(NoContext, epoch.as_secs(), epoch.subsec_nanos());
+    uuid::Uuid::new_v7(ts).hyphenated().to_string()
+}
+
+fn index_text(conn: &Connection, id: &str, source: &str, text: &str) -> Result<()> {
+    conn.execute("DELETE FROM content_fts WHERE bmark_id=?1 AND source=?2", params![id, source])?;
+    if !text.trim().is_empty() {
+        conn.execute(
+            "INSERT INTO content_fts (bmark_id, source, body) VALUES (?1, ?2, ?3)",
+            params![id, source, text],
+        )?;
+    }
+    Ok(())
+}
+
+fn add_bookmark(tx: &Transaction, journal: &mut Journal, new: &NewBookmark) -> Result<String> {
+    let bmark_uuid = new.id.clone().unwrap_or_else(new_uuid);
+    journal.touch(tx, "bmark", &[("id", &bmark_uuid)])?;
+    tx.execute(
+        "INSERT INTO bmark (id, url, name, description, category, added_at, deleted_at, status, unread_at, reading_at, read_at, archived_at, pinned, rating) \
+         VALUES (?1, ?2, ?3, ?4, ?5, coalesce(?6, current_timestamp), ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+        params![
+            bmark_uuid,
+            new.url,
+            new.name,
+            new.desc,
+            new.category,
+            new.added_at,
+            new.deleted_at,
+            new.status.map(|s| s.as_str()),
+            new.unread_at,
+            new.reading_at,
+            new.read_at,
+            new.archived_at,
+            new.pinned,
+            new.rating
+        ],
+    )?;
+    if let Some(status) = new.status {
+        tx.execute(
+            &format!("UPDATE bmark SET {0}=added_at WHERE id=?1 AND {0} IS NULL", status.column()),
+            params![bmark_uuid],
+        )?;
+    }
+
+    // make bmark-tag relation
+    for tag in &new.tags {
+        attach_tag(tx, journal, &bmark_uuid, tag)?;
+    }
+    for note in &new.notes {
+        journal.touch(tx, "note", &[("bmark_id", &bmark_uuid), ("seq", &note.seq.to_string())])?;
+        tx.execute(
+            "INSERT INTO note (bmark_id, seq, body, created_at, updated_at) VALUES (?1, ?2, ?3, coalesce(?4, current_timestamp), ?5)",
+            params![bmark_uuid, note.seq, note.body, Some(&note.created_at).filter(|at| !at.is_empty()), note.updated_at],
+        )?;
+        index_text(tx, &bmark_uuid, &format!("note:{}", note.seq), &note.body)?;
+    }
+    for alternate in &new.alternates {
+        journal.touch(tx, "alternate", &[("bmark_id", &bmark_uuid), ("url", &alternate.url)])?;
+        tx.execute(
+            "INSERT OR REPLACE INTO alternate (bmark_id, url, source, captured_at, added_at) VALUES (?1, ?2, ?3, ?4, coalesce(?5, current_timestamp))",
+            params![bmark_uuid, alternate.url, alternate.source, alternate.captured_at, Some(&alternate.added_at).filter(|at| !at.is_empty())],
+        )?;
+    }
+    if let Some(reminder) = &new.reminder {
+        journal.touch(tx, "reminder", &[("bmark_id", &bmark_uuid)])?;
+        tx.execute(
+            "INSERT INTO reminder (bmark_id, due_at, created_at, snoozed) VALUES (?1, ?2, coalesce(?3, current_timestamp), ?4)",
+            params![bmark_uuid, reminder.due_at, Some(&reminder.created_at).filter(|at| !at.is_empty()), reminder.snoozed],
+        )?;
+    }
+    for visited_at in &new.visits {
+        let id = next_id(tx, "visit")?;
+        journal.touch(tx, "visit", &[("id", &id.to_string())])?;
+        tx.execute("INSERT INTO visit (id, bmark_id, visited_at) VALUES (?1, ?2, ?3)", params![id, bmark_uuid, visited_at])?;
+    }
+    if let Some(meta) = &new.page_meta {
+        journal.touch(tx, "page_meta", &[("bmark_id", &bmark_uuid)])?;
+        tx.execute(
+            "INSERT OR REPLACE INTO page_meta (bmark_id, title, description, canonical_url, lang, fetched_at) \
+             VALUES (?1, ?2, ?3, ?4, ?5, coalesce(?6, current_timestamp))",
+            params![bmark_uuid, meta.title, meta.description, meta.canonical_url, meta.lang, Some(&meta.fetched_at).filter(|at| !at.is_empty())],
+        )?;
+    }
+    for revision in &new.revisions {
+        journal.touch(tx, "revision", &[("bmark_id", &bmark_uuid), ("rev", &revision.rev.to_string())])?;
+        tx.execute(
+            "INSERT INTO revision (bmark_id, rev, url, name, description, category, tags, created_at) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, coalesce(?8, current_timestamp))",
+            params![
+                bmark_uuid,
+                revision.rev,
+                revision.url,
+                revision.name,
+                revision.desc,
+                revision.category,
+                serde_json::to_string(&revision.tags)?,
+                Some(&revision.created_at).filter(|at| !at.is_empty())
+            ],
+        )?;
+    }
+    // oldest first, so the latest check keeps the highest id
+    for status in new.link_statuses.iter().rev() {
+        let id = next_id(tx, "link_status")?;
+        journal.touch(tx, "link_status", &[("id", &id.to_string())])?;
+        tx.execute(
+            "INSERT INTO link_status (id, bmark_id, url, status, final_url, permanent, error, checked_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![id, bmark_uuid, status.url, status.status, status.final_url, status.permanent, status.error, status.checked_at],
+        )?;
+    }
+    record_revision(tx, journal, &bmark_uuid)?;
+
+    Ok(bmark_uuid)
+}
+
+fn apply_edit(tx: &Transaction, journal: &mut Journal, id: &str, edit: &BookmarkEdit) -> Result<()> {
+    journal.touch(tx, "bmark", &[("id", id)])?;
+
+    let exists = tx
+        .query_row("SELECT 1 FROM bmark WHERE id=?1 AND deleted_at IS NULL", params![id], |_| Ok(()))
+        .optional()?;
+    ensure!(exists.is_some(), "No bookmark with id {}", id);
+
+    if let Some(url) = &edit.url {
+        ensure!(!url.is_empty(), "URL of a bookmark can't be empty");
+        tx.execute("UPDATE bmark SET url=?1 WHERE id=?2", params![url, id])?;
+    }
+    let fields = [("name", &edit.name), ("description", &edit.desc), ("category", &edit.category)];
+    for (column, value) in fields {
+        if let Some(value) = value {
+            let value = Some(value.as_str()).filter(|v| !v.is_empty());
+            tx.execute(&format!("UPDATE bmark SET {}=?1 WHERE id=?2", column), params![value, id])?;
+        }
+    }
+    for tag in &edit.add_tags {
+        attach_tag(tx, journal, id, tag)?;
+    }
+    for tag in &edit.remove_tags {
+        let condition = "bmark_id=?1 AND tag_id=(SELECT id FROM tag WHERE name=?2)";
+        journal.touch_where(tx, "bmark_tag", condition, params![id, tag])?;
+        tx.execute(&format!("DELETE FROM bmark_tag WHERE {}", condition), params![id, tag])?;
+    }
+
+    record_revision(tx, journal, id)
+}
+
+/// Current fields and (sorted) tags of every bookmark, shaped like a `revision` row
+const REVISION_SNAPSHOT: &str = "SELECT b.id AS bmark_id, b.url AS url, b.name AS name, b.description AS description, b.category AS category, \
+    (SELECT json_group_array(name) FROM (SELECT t.name FROM bmark_tag bt JOIN tag t ON t.id=bt.tag_id WHERE bt.bmark_id=b.id ORDER BY t.name)) AS tags \
+    FROM bmark b";
+
+/// Add a revision for the current state of the bookmark unless it matches the latest one
+fn record_revision(tx: &Transaction, journal: &mut Journal, bmark_id: &str) -> Result<()> {
+    let latest: Option<i64> = tx.query_row(
+        "SELECT max(rev) FROM revision WHERE bmark_id=?1",
+        params![bmark_id],
+        |row| row.get(0),
+    )?;
+    if let Some(latest) = latest {
+        let unchanged = tx
+            .query_row(
+                &format!(
+                    "SELECT 1 FROM ({}) s JOIN revision r ON r.bmark_id=s.bmark_id AND r.rev=?2 \
+                     WHERE s.bmark_id=?1 AND r.url IS s.url AND r.name IS s.name \
+                     AND r.description IS s.description AND r.category IS s.category AND r.tags IS s.tags",
+                    REVISION_SNAPSHOT
+                ),
+                params![bmark_id, latest],
+                |_| Ok(()),
+            )
+            .optional()?;
+        if unchanged.is_some() {
+            return Ok(());
+        }
+    }
+
+    let rev = latest.unwrap_or(0) + 1;
+    journal.touch(tx, "revision", &[("bmark_id", bmark_id), ("rev", &rev.to_string())])?;
+    tx.execute(
+        &format!(
+            "INSERT INTO revision (bmark_id, url, name, description, category, tags, rev) \
+             SELECT s.*, ?2 FROM ({}) s WHERE s.bmark_id=?1",
+            REVISION_SNAPSHOT
+        ),
+        params![bmark_id, rev],
+    )?;
+    Ok(())
+}
+
+/// Id the next row of the autoincrement `table` gets, so the row can be journaled before it's
+/// inserted
+fn next_id(tx: &Transaction, table: &str) -> Result<i64> {
+    Ok(tx.query_row(
+        &format!("SELECT max(coalesce((SELECT seq FROM sqlite_sequence WHERE name=?1), 0), coalesce((SELECT max(id) FROM {}), 0)) + 1", table),
+        params![table],
+        |row| row.get(0),
+    )?)
+}
+
+/// Elements of a json array of strings
+fn json_array(conn: &Connection, array: &str) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT value FROM json_each(?1)")?;
+    let rows = stmt.query_map(params![array], |row| row.get(0))?;
+    Ok(rows.collect::<Result<Vec<_>, _>>()?)
+}
+
+/// Tag the bookmark with `tag`, creating the tag if it doesn't exist yet
+fn attach_tag(tx: &Transaction, journal: &mut Journal, bmark_id: &str, tag: &str) -> Result<()> {
+    let tag_id = match tx
+        .query_row("SELECT id FROM tag WHERE name=?1", params![tag], |row| row.get::<_, String>(0))
+        .optional()?
+    {
+        Some(tag_id) => tag_id,
+        None => {
+            let tag_id = new_uuid();
+            journal.touch(tx, "tag", &[("id", &tag_id)])?;
+            tx.execute("INSERT INTO tag (id, name) VALUES(?1, ?2)", params![tag_id, tag])?;
+            tag_id
+        }
+    };
+    journal.touch(tx, "bmark_tag", &[("bmark_id", bmark_id), ("tag_id", &tag_id)])?;
+    tx.execute(
+        "INSERT OR IGNORE INTO bmark_tag (bmark_id, tag_id) VALUES(?1, ?2)",
+        params![bmark_id, tag_id],
+    )?;
+    Ok(())
 }
 
 // Perform db operation
 fn get_db_connection(path: Option<&PathBuf>) -> Result<Connection> {
     match path {
-        Some(p) => Connection::open(p).with_context(|| format!("Couldn't open connection to db")),
+        Some(p) => Connection::open(p).context("Couldn't open connection to db"),
         None => Connection::open_in_memory()
-            .with_context(|| format!("Couldn't open connection to db in memory")),
+            .context("Couldn't open connection to db in memory"),
     }
 }
 
@@ -375,22 +1547,22 @@ pub fn is_setup_done() -> Result<bool> {
     if !path.exists() {
         return Ok(false);
     }
-    let tables = vec!["bmark", "tag", "bmark_tag"];
+    let tables = ["bmark", "tag", "bmark_tag"];
     let conn = get_db_connection(Some(&path.to_path_buf())).unwrap();
     let mut stmt = conn.prepare("SELECT name FROM sqlite_master WHERE name=?1")?;
-    let res_bmark = stmt.query_row(&[tables[0]], |row| row.get::<_, String>(0));
+    let res_bmark = stmt.query_row([tables[0]], |row| row.get::<_, String>(0));
     if res_bmark == Err(QueryReturnedNoRows) {
         return Ok(false);
     }
-    let res_tag = stmt.query_row(&[tables[1]], |row| row.get::<_, String>(0));
+    let res_tag = stmt.query_row([tables[1]], |row| row.get::<_, String>(0));
     if res_tag == Err(QueryReturnedNoRows) {
         return Ok(false)
     }
-    let res_bmark_tag = stmt.query_row(&[tables[2]], |row| row.get::<_, String>(0));
+    let res_bmark_tag = stmt.query_row([tables[2]], |row| row.get::<_, String>(0));
     if res_bmark_tag == Err(QueryReturnedNoRows) {
         return Ok(false); }
 
-    return Ok(true);
+    Ok(true)
 }
 
 #[test]
@@ -402,9 +1574,424 @@ fn is_table_created() -> Result<()> {
 
     // query whether table exists
     let mut stmt = conn.prepare("SELECT name FROM sqlite_master WHERE name=?1")?;
-    let res = stmt.query_row(&[table_name], |row| row.get::<_, String>(0))?;
+    let res = stmt.query_row([table_name], |row| row.get::<_, String>(0))?;
 
     assert_eq!(table_name, &res);
 
     Ok(())
 }
+
+#[test]
+fn trash_and_restore() -> Result<()> {
+    let mut bmark = BMark { conn: get_db_connection(None)? };
+    bmark.setup()?;
+    let id = bmark.insert("https://example.com", Some("example"), vec!["web"], None, None)?;
+
+    bmark.trash(&id)?;
+    assert!(bmark.bookmarks()?.is_empty());
+    assert_eq!(bmark.trashed()?[0].tags, vec!["web"]);
+
+    bmark.restore(&id)?;
+    assert_eq!(bmark.bookmarks()?.len(), 1);
+    assert!(bmark.restore(&id).is_err());
+
+    bmark.trash(&id)?;
+    assert_eq!(bmark.empty_trash(Some(86400))?, 0);
+    assert_eq!(bmark.empty_trash(None)?, 1);
+    assert!(bmark.trashed()?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn undo_reverts_whole_operation() -> Result<()> {
+    let mut bmark = BMark { conn: get_db_connection(None)? };
+    bmark.setup()?;
+    let id = bmark.insert("https://example.com", Some("example"), vec!["web", "docs"], None, None)?;
+    bmark.insert("https://rust-lang.org", None, vec!["lang"], None, None)?;
+
+    bmark.merge_tags("docs", "web")?;
+    bmark.edit(&id, &BookmarkEdit { name: Some(String::new()), ..Default::default() })?;
+    bmark.trash(&id)?;
+    assert_eq!(bmark.history(10)?.len(), 5);
+
+    let undone = bmark.undo(3)?;
+    assert_eq!(undone.iter().map(|o| o.kind.as_str()).collect::<Vec<_>>(), ["rm", "edit", "tag merge"]);
+    let restored = bmark.bookmark(&id)?;
+    assert_eq!(restored.name.as_deref(), Some("example"));
+    assert_eq!(restored.tags, vec!["docs", "web"]);
+
+    // undoing both adds leaves nothing behind, not even their tags
+    bmark.undo(2)?;
+    assert!(bmark.bookmarks()?.is_empty());
+    assert!(bmark.undo(1)?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn merge_tag_into_itself() -> Result<()> {
+    let mut bmark = BMark { conn: get_db_connection(None)? };
+    bmark.setup()?;
+    let id = bmark.insert("https://example.com", None, vec!["web"], None, None)?;
+
+    let err = bmark.merge_tags("web", "web").unwrap_err();
+    assert_eq!(err.to_string(), "Can't merge a tag into itself");
+    assert_eq!(bmark.bookmark(&id)?.tags, vec!["web"]);
+    assert_eq!(bmark.history(10)?.len(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn revisions_and_revert() -> Result<()> {
+    let mut bmark = BMark { conn: get_db_connection(None)? };
+    bmark.setup()?;
+    let id = bmark.insert("https://example.com", Some("example"), vec!["web"], None, None)?;
+
+    bmark.edit(&id, &BookmarkEdit { name: Some("Example".into()), add_tags: vec!["docs".into()], ..Default::default() })?;
+    // nothing changes, so no new revision
+    bmark.edit(&id, &BookmarkEdit { name: Some("Example".into()), ..Default::default() })?;
+    let revisions = bmark.revisions(&id)?;
+    assert_eq!(revisions.len(), 2);
+    assert_eq!(revisions[1].tags, vec!["docs", "web"]);
+
+    bmark.revert(&id, 1)?;
+    let reverted = bmark.bookmark(&id)?;
+    assert_eq!(reverted.name.as_deref(), Some("example"));
+    assert_eq!(reverted.tags, vec!["web"]);
+    assert_eq!(bmark.revisions(&id)?.len(), 3);
+
+    // undoing the revert drops the revision it wrote as well
+    bmark.undo(1)?;
+    assert_eq!(bmark.revisions(&id)?.len(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn resolve_by_prefix_and_url() -> Result<()> {
+    let mut bmark = BMark { conn: get_db_connection(None)? };
+    bmark.setup()?;
+    let first = bmark.insert("https://example.com", None, vec![], None, None)?;
+    let second = bmark.insert("https://rust-lang.org", None, vec![], None, None)?;
+
+    assert_eq!(bmark.resolve(&first)?, first);
+    assert_eq!(bmark.resolve("https://rust-lang.org")?, second);
+    assert_eq!(bmark.resolve(&second[..second.len() - 2])?, second);
+    // ids created close together share their leading timestamp digits
+    let common = first.chars().zip(second.chars()).take_while(|(a, b)| a == b).count();
+    assert!(bmark.resolve(&first[..common]).is_err());
+    assert!(bmark.resolve("abc").is_err());
+
+    // trashed bookmarks only resolve for restoring, and don't make prefixes ambiguous
+    bmark.trash(&first)?;
+    assert!(bmark.resolve(&first).is_err());
+    assert!(bmark.resolve("https://example.com").is_err());
+    assert_eq!(bmark.resolve(&first[..common])?, second);
+    assert_eq!(bmark.resolve_trashed(&first[..first.len() - 2])?, first);
+    assert!(bmark.resolve_trashed(&second).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn sort_by_visits() -> Result<()> {
+    let mut bmark = BMark { conn: get_db_connection(None)? };
+    bmark.setup()?;
+    let first = bmark.insert("https://example.com", None, vec![], None, None)?;
+    let second = bmark.insert("https://rust-lang.org", None, vec!["lang"], None, None)?;
+    bmark.record_visit(&second)?;
+    bmark.record_visit(&second)?;
+    bmark.record_visit(&first)?;
+
+    let by_visits = bmark.query(&ListOptions { sort: SortBy::Visits, ..Default::default() })?;
+    assert_eq!(by_visits.iter().map(|b| b.id.as_str()).collect::<Vec<_>>(), [&second, &first]);
+    assert_eq!(bmark.search("LANG")?.len(), 1);
+
+    bmark.trash(&second)?;
+    assert_eq!(bmark.empty_trash(None)?, 1);
+
+    Ok(())
+}
+
+#[test]
+fn filter_by_tags_and_category() -> Result<()> {
+    let mut bmark = BMark { conn: get_db_connection(None)? };
+    bmark.setup()?;
+    let docs = bmark.insert("https://docs.rs", None, vec!["rust", "docs"], None, Some("dev/rust"))?;
+    let book = bmark.insert("https://doc.rust-lang.org/book", None, vec!["rust"], None, Some("dev"))?;
+    bmark.insert("https://developer.mozilla.org", None, vec!["docs"], None, Some("devtools"))?;
+
+    let ids = |options: ListOptions| -> Result<Vec<String>> {
+        let mut ids = bmark.query(&options)?.into_iter().map(|b| b.id).collect::<Vec<_>>();
+        ids.sort();
+        Ok(ids)
+    };
+    let mut both = vec![docs.clone(), book.clone()];
+    both.sort();
+    let tags = vec![String::from("rust"), String::from("docs")];
+    assert_eq!(ids(ListOptions { tags: vec![String::from("rust")], ..Default::default() })?, both);
+    assert_eq!(ids(ListOptions { tags: tags.clone(), ..Default::default() })?.len(), 3);
+    assert_eq!(ids(ListOptions { tags, tag_mode: TagMode::All, ..Default::default() })?, [docs.as_str()]);
+    assert_eq!(ids(ListOptions { category: Some(String::from("dev")), ..Default::default() })?, both);
+    assert_eq!(ids(ListOptions { category: Some(String::from("dev/rust")), ..Default::default() })?, [docs.as_str()]);
+
+    Ok(())
+}
+
+#[test]
+fn check_links_and_fix_redirects() -> Result<()> {
+    use check::{check, CheckOptions};
+    use fetch::{response, serve};
+
+    let base = serve(|path| match path {
+        "/ok" => response("200 OK", &[], ""),
+        "/moved" => response("301 Moved Permanently", &[("Location", "/ok")], ""),
+        "/temp" => response("302 Found", &[("Location", "/ok")], ""),
+        _ => response("404 Not Found", &[], ""),
+    });
+    let mut bmark = BMark { conn: get_db_connection(None)? };
+    bmark.setup()?;
+    let mut ids = HashMap::new();
+    for path in ["/ok", "/moved", "/temp", "/gone"] {
+        ids.insert(path, bmark.insert(&format!("{}{}", base, path), None, vec![], None, None)?);
+    }
+
+    let links = bmark.bookmarks()?.into_iter().map(|b| (b.id, b.url)).collect();
+    let options = CheckOptions { per_host_delay: std::time::Duration::ZERO, ..Default::default() };
+    check(links, &options, |s| bmark.record_link_status(s))?;
+    let picked = |bmark: &BMark, link| -> Result<Vec<String>> {
+        let options = ListOptions { link: Some(link), ..Default::default() };
+        Ok(bmark.query(&options)?.into_iter().map(|b| b.url.replace(&base, "")).collect())
+    };
+    assert_eq!(picked(&bmark, LinkFilter::Broken)?, ["/gone"]);
+    let mut redirected = picked(&bmark, LinkFilter::Redirected)?;
+    redirected.sort();
+    assert_eq!(redirected, ["/moved", "/temp"]);
+    assert_eq!(bmark.link_statuses(&ids["/gone"])?[0].status, Some(404));
+
+    // only the permanent redirect gets followed
+    let fixed = bmark.fix_redirects()?;
+    assert_eq!(fixed.len(), 1);
+    assert_eq!(bmark.bookmark(&ids["/moved"])?.url, format!("{}/ok", base));
+    assert_eq!(picked(&bmark, LinkFilter::Redirected)?, ["/temp"]);
+    bmark.undo(1)?;
+    assert_eq!(bmark.bookmark(&ids["/moved"])?.url, format!("{}/moved", base));
+
+    // a check tells about the URL it requested, not about the one the bookmark got since
+    let edit = BookmarkEdit { url: Some(format!("{}/elsewhere", base)), ..Default::default() };
+    bmark.edit(&ids["/temp"], &edit)?;
+    bmark.edit(&ids["/moved"], &edit)?;
+    assert!(picked(&bmark, LinkFilter::Redirected)?.is_empty());
+    assert!(bmark.fix_redirects()?.is_empty());
+    assert_eq!(bmark.link_statuses(&ids["/moved"])?[0].url, format!("{}/moved", base));
+
+    Ok(())
+}
+
+#[test]
+fn search_indexed_page_text() -> Result<()> {
+    let mut bmark = BMark { conn: get_db_connection(None)? };
+    bmark.setup()?;
+    let rust = bmark.insert("https://rust-lang.org", Some("Rust"), vec![], None, None)?;
+    let cafe = bmark.insert("https://example.com/cafe", Some("Cafe"), vec![], None, None)?;
+    bmark.index_text(&rust, "page", "A language empowering everyone to build reliable and efficient software.")?;
+    bmark.index_text(&cafe, "page", "The café serves efficient espresso.\nOpen daily.")?;
+
+    let found = bmark.search_content("efficient software")?;
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].0.id, rust);
+    assert!(found[0].1.contains("[efficient] [software]"));
+    // accents and case don't matter, and fts5 syntax is taken literally
+    let found = bmark.search_content("CAFE \"espresso")?;
+    assert_eq!(found.iter().map(|(b, _)| b.id.as_str()).collect::<Vec<_>>(), [cafe.as_str()]);
+
+    // indexing again replaces the text
+    bmark.index_text(&cafe, "page", "Closed for good.")?;
+    assert!(bmark.search_content("espresso")?.is_empty());
+    bmark.trash(&rust)?;
+    assert!(bmark.search_content("software")?.is_empty());
+    bmark.empty_trash(None)?;
+    assert_eq!(bmark.conn.query_row("SELECT count(*) FROM content_fts", [], |row| row.get::<_, i64>(0))?, 1);
+    Ok(())
+}
+
+#[test]
+fn rescue_keeps_the_archived_copy() -> Result<()> {
+    struct Stub;
+    impl ArchiveProvider for Stub {
+        fn name(&self) -> &str {
+            "stub"
+        }
+        fn closest(&self, url: &str, at: &date::Datetime) -> Result<Option<ArchivedCopy>> {
+            Ok(url.ends_with("/gone").then(|| ArchivedCopy {
+                url: format!("https://archive.example/{}/{}", at.digits(), url),
+                captured_at: at.to_string(),
+            }))
+        }
+    }
+
+    let mut bmark = BMark { conn: get_db_connection(None)? };
+    bmark.setup()?;
+    let gone = bmark.insert("https://example.com/gone", None, vec![], None, None)?;
+    let never = bmark.insert("https://example.com/never", None, vec![], None, None)?;
+    let added_at = bmark.bookmark(&gone)?.added_at;
+
+    let copy = bmark.rescue(&gone, &Stub)?.unwrap();
+    assert_eq!(copy.captured_at, added_at);
+    assert!(bmark.rescue(&never, &Stub)?.is_none());
+    let alternates = bmark.alternates(&gone)?;
+    assert_eq!(alternates.len(), 1);
+    assert_eq!((alternates[0].url.as_str(), alternates[0].source.as_str()), (copy.url.as_str(), "stub"));
+
+    // rescuing again keeps the one link
+    bmark.rescue(&gone, &Stub)?;
+    assert_eq!(bmark.alternates(&gone)?.len(), 1);
+    bmark.undo(2)?;
+    assert!(bmark.alternates(&gone)?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn favicons_are_cached_per_domain() -> Result<()> {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use fetch::{response, serve};
+
+    let requests = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&requests);
+    let base = serve(move |path| {
+        counter.fetch_add(1, Ordering::SeqCst);
+        match path {
+            "/favicon.ico" => response("200 OK", &[("Content-Type", "image/png")], "png"),
+            _ => response("200 OK", &[("Content-Type", "text/html")], "<title>Page</title>"),
+        }
+    });
+    let mut bmark = BMark { conn: get_db_connection(None)? };
+    bmark.setup()?;
+    let options = FaviconOptions::default();
+
+    let icon = bmark.favicon(&format!("{}/a", base), &options)?.unwrap();
+    assert_eq!(icon.mime, "image/png");
+    // another page of the same site comes from the cache
+    assert_eq!(bmark.favicon(&format!("{}/b", base), &options)?, Some(icon));
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+    // an expired entry is fetched again
+    bmark.favicon(&format!("{}/b", base), &FaviconOptions { max_age: std::time::Duration::ZERO, ..options })?;
+    assert_eq!(requests.load(Ordering::SeqCst), 4);
+    Ok(())
+}
+
+#[test]
+fn reading_queue() -> Result<()> {
+    let mut bmark = BMark { conn: get_db_connection(None)? };
+    bmark.setup()?;
+    let first = bmark.add(
+        &NewBookmark {
+            url: "https://example.com/first".into(),
+            added_at: Some("2020-01-01 00:00:00".into()),
+            status: Some(ReadStatus::Unread),
+            ..Default::default()
+        },
+        None,
+    )?;
+    let second = bmark.insert("https://example.com/second", None, vec![], None, None)?;
+    assert_eq!(bmark.bookmark(&first)?.unread_at.as_deref(), Some("2020-01-01 00:00:00"));
+    assert_eq!(bmark.bookmark(&second)?.status, None);
+
+    bmark.set_status(&second, ReadStatus::Unread)?;
+    assert_eq!(bmark.next_unread(false)?.map(|b| b.id), Some(first.clone()));
+    bmark.set_status(&first, ReadStatus::Read)?;
+    let done = bmark.bookmark(&first)?;
+    assert_eq!(done.status, Some(ReadStatus::Read));
+    assert!(done.read_at.is_some() && done.unread_at.is_some());
+    assert_eq!(bmark.next_unread(true)?.map(|b| b.id), Some(second.clone()));
+
+    let unread = bmark.query(&ListOptions { status: Some(ReadStatus::Unread), ..Default::default() })?;
+    assert_eq!(unread.iter().map(|b| b.id.as_str()).collect::<Vec<_>>(), [second.as_str()]);
+    bmark.undo(1)?;
+    assert_eq!(bmark.bookmark(&first)?.status, Some(ReadStatus::Unread));
+    Ok(())
+}
+
+#[test]
+fn pinned_first_and_min_rating() -> Result<()> {
+    let mut bmark = BMark { conn: get_db_connection(None)? };
+    bmark.setup()?;
+    let ids = ["a", "b", "c"]
+        .iter()
+        .map(|name| bmark.insert(&format!("https://example.com/{}", name), Some(name), vec![], None, None))
+        .collect::<Result<Vec<_>>>()?;
+    bmark.pin(&ids[2], true)?;
+    bmark.rate(&ids[1], Some(4))?;
+    bmark.rate(&ids[2], Some(3))?;
+    assert!(bmark.rate(&ids[0], Some(6)).is_err());
+
+    let listed = bmark.bookmarks()?.into_iter().map(|b| b.id).collect::<Vec<_>>();
+    assert_eq!(listed, [ids[2].clone(), ids[0].clone(), ids[1].clone()]);
+    let rated = bmark.query(&ListOptions { min_rating: Some(3), ..Default::default() })?;
+    assert_eq!(rated.iter().map(|b| (b.id.as_str(), b.rating)).collect::<Vec<_>>(), [(ids[2].as_str(), Some(3)), (ids[1].as_str(), Some(4))]);
+    let pinned = bmark.query(&ListOptions { pinned: true, ..Default::default() })?;
+    assert_eq!(pinned.len(), 1);
+    assert!(pinned[0].pinned);
+
+    bmark.pin(&ids[2], false)?;
+    bmark.rate(&ids[1], None)?;
+    assert!(bmark.query(&ListOptions { min_rating: Some(1), ..Default::default() })?.iter().all(|b| b.id == ids[2]));
+    Ok(())
+}
+
+#[test]
+fn notes_are_searchable_and_undoable() -> Result<()> {
+    let mut bmark = BMark { conn: get_db_connection(None)? };
+    bmark.setup()?;
+    let id = bmark.insert("https://example.com/paper", Some("Paper"), vec![], None, None)?;
+    assert_eq!(bmark.add_note(&id, "Section 3 has the **proof**.")?, 1);
+    assert_eq!(bmark.add_note(&id, "Compare with the follow-up paper")?, 2);
+    bmark.edit_note(&id, 1, "Section 4 has the **lemma**.")?;
+    assert!(bmark.edit_note(&id, 3, "nothing").is_err());
+
+    let notes = bmark.notes(&id)?;
+    assert_eq!(notes.iter().map(|n| (n.seq, n.body.as_str())).collect::<Vec<_>>(), [(1, "Section 4 has the **lemma**."), (2, "Compare with the follow-up paper")]);
+    assert!(notes[0].updated_at.is_some() && notes[1].updated_at.is_none());
+    assert_eq!(bmark.search_content("lemma")?.len(), 1);
+    assert!(bmark.search_content("proof")?.is_empty());
+    assert_eq!(bmark.search("follow-up")?.len(), 1);
+
+    bmark.undo(1)?;
+    assert_eq!(bmark.notes(&id)?[0].body, "Section 3 has the **proof**.");
+    assert_eq!(bmark.search_content("proof")?.len(), 1);
+    assert!(bmark.search_content("lemma")?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn reminders_come_due_and_snooze() -> Result<()> {
+    let mut bmark = BMark { conn: get_db_connection(None)? };
+    bmark.setup()?;
+    let rfc = bmark.insert("https://www.rfc-editor.org/rfc/rfc9110", Some("HTTP Semantics"), vec![], None, None)?;
+    let notes = bmark.insert("https://blog.rust-lang.org/releases/", Some("Releases"), vec![], None, None)?;
+    bmark.remind(&rfc, &date::parse_when("on 2025-01-10")?)?;
+    bmark.remind(&notes, &date::parse_when("on 2025-03-01")?)?;
+
+    let at = date::parse_date("2025-02-01 12:00:00")?;
+    let due = bmark.due(&at)?;
+    assert_eq!(due.iter().map(|(b, r)| (b.id.as_str(), r.due_at.as_str())).collect::<Vec<_>>(), [(rfc.as_str(), "2025-01-10 00:00:00")]);
+
+    bmark.snooze(&rfc, &date::parse_date("2025-02-08 00:00:00")?)?;
+    assert!(bmark.due(&at)?.is_empty());
+    assert_eq!(bmark.reminder(&rfc)?.map(|r| r.snoozed), Some(1));
+    bmark.dismiss(&notes)?;
+    assert!(bmark.reminder(&notes)?.is_none());
+    assert!(bmark.snooze(&notes, &at).is_err());
+
+    bmark.undo(1)?;
+    assert!(bmark.reminder(&notes)?.is_some());
+    bmark.trash(&rfc)?;
+    assert!(bmark.due(&date::parse_date("2026-01-01 00:00:00")?)?.iter().all(|(b, _)| b.id == notes));
+    Ok(())
+}
@@ -1,10 +1,309 @@
-use std::path::PathBuf;
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    fs::{self, File},
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+    process::Command,
+    time::Duration,
+};
 
-use anyhow::{Context, Result};
-use bmark_rs::{is_setup_done, BMark, ListColumn, OutputType};
+use anyhow::{bail, Context, Result};
+use bmark_rs::{archive, browser, check::{self, CheckOptions}, date, export, favicon::{FaviconOptions, Icon}, feed, fetch::{self, FetchOptions}, import::{self, csv::CsvOptions, Batch, ImportReport}, is_setup_done, publish, rescue::Wayback, BMark, Bookmark, BookmarkEdit, LinkFilter, ListColumn, ListOptions, NewBookmark, Note, OutputType, ReadStatus, Revision, SortBy, TagMode};
+use clap::ArgMatches;
 
 mod cli;
-mod date;
+
+/// Open the bookmark db, telling the user to run setup first if it isn't there yet
+fn open_bmark() -> Result<Option<BMark>> {
+    if is_setup_done()? {
+        Ok(Some(BMark::new("./local/bmark/bmark.db", false)?))
+    } else {
+        println!("You need to do setup first. Run: bmark setup --help for more info");
+        Ok(None)
+    }
+}
+
+fn print_bookmark(b: &Bookmark) {
+    println!("id:       {}", b.id);
+    println!("url:      {}", b.url);
+    println!("name:     {}", b.name.as_deref().unwrap_or_default());
+    println!("tags:     {}", b.tags.join(", "));
+    println!("desc:     {}", b.desc.as_deref().unwrap_or_default());
+    println!("category: {}", b.category.as_deref().unwrap_or_default());
+    match date::parse_date(&b.added_at) {
+        Ok(added_at) => println!("added_at: {}", added_at.readable()),
+        Err(_) => println!("added_at: {}", b.added_at),
+    }
+    if let Some(status) = b.status {
+        let since = match status {
+            ReadStatus::Unread => &b.unread_at,
+            ReadStatus::Reading => &b.reading_at,
+            ReadStatus::Read => &b.read_at,
+            ReadStatus::Archived => &b.archived_at,
+        };
+        println!("status:   {} since {}", status.as_str(), since.as_deref().unwrap_or_default());
+    }
+    if b.pinned {
+        println!("pinned:   yes");
+    }
+    if let Some(rating) = b.rating {
+        println!("rating:   {}{}", "★".repeat(rating as usize), "☆".repeat(5 - rating as usize));
+    }
+    if let Some(deleted_at) = &b.deleted_at {
+        println!("trashed:  {}", deleted_at);
+    }
+}
+
+/// Print, field by field, the revisions in which the field took a new value
+fn print_timeline(revisions: &[Revision]) {
+    let value_of = |r: &Revision, field: &str| match field {
+        "url" => r.url.clone(),
+        "name" => r.name.clone().unwrap_or_default(),
+        "desc" => r.desc.clone().unwrap_or_default(),
+        "category" => r.category.clone().unwrap_or_default(),
+        _ => r.tags.join(", "),
+    };
+    for field in ["url", "name", "desc", "category", "tags"] {
+        println!("{}", field);
+        let mut last: Option<String> = None;
+        for revision in revisions {
+            let value = value_of(revision, field);
+            if last.as_ref() != Some(&value) {
+                let shown = if value.is_empty() { "-" } else { &value };
+                println!("  rev {:<3} {}  {}", revision.rev, revision.created_at, shown);
+                last = Some(value);
+            }
+        }
+    }
+}
+
+/// Let the user choose one of several matching bookmarks
+fn pick(matches: Vec<Bookmark>) -> Result<Bookmark> {
+    for (i, b) in matches.iter().enumerate() {
+        println!("{:>3}) {} {}", i + 1, b.name.as_deref().unwrap_or_default(), b.url);
+    }
+    print!("Pick a bookmark [1-{}]: ", matches.len());
+    io::stdout().flush()?;
+
+    let mut choice = String::new();
+    io::stdin().lock().read_line(&mut choice)?;
+    match choice.trim().parse::<usize>() {
+        Ok(n) if n >= 1 && n <= matches.len() => Ok(matches.into_iter().nth(n - 1).unwrap()),
+        _ => bail!("No bookmark picked"),
+    }
+}
+
+/// Add the bookmark, with the title, description and text of its page when `timeout` says
+/// how long to wait for it. What `new` already has wins over what the page says.
+fn add_fetched(bmark: &mut BMark, mut new: NewBookmark, timeout: Option<u64>) -> Result<String> {
+    let page = timeout.and_then(|timeout| {
+        let options = FetchOptions { timeout: Duration::from_secs(timeout), ..Default::default() };
+        fetch::metadata(&new.url, &options)
+            .map_err(|e| eprintln!("Adding without the page's metadata: {:#}", e))
+            .ok()
+    });
+    if let Some((meta, _)) = &page {
+        new.name = new.name.or(meta.name().map(String::from));
+        new.desc = new.desc.or(meta.description.clone());
+    }
+    let id = bmark.add(&new, page.as_ref().map(|(meta, _)| meta))?;
+    if let Some((_, text)) = &page {
+        bmark.index_text(&id, "page", text)?;
+    }
+    Ok(id)
+}
+
+/// Text of a note given on the command line, `-` meaning stdin
+fn note_text(matches: &ArgMatches) -> Result<Option<String>> {
+    let Some(words) = matches.get_many::<String>("text") else { return Ok(None) };
+    let words = words.cloned().collect::<Vec<_>>();
+    if words == ["-"] {
+        return Ok(Some(io::read_to_string(io::stdin())?));
+    }
+    Ok(Some(words.join(" ")))
+}
+
+/// Let the user rewrite `text` in their editor, returning what they saved
+fn edit_in_editor(text: &str) -> Result<String> {
+    let editor = env::var("VISUAL").or_else(|_| env::var("EDITOR")).unwrap_or_else(|_| String::from("vi"));
+    // a directory only we can enter, made fresh so nobody could have put a link where the note goes
+    let dir = env::temp_dir().join(format!("bmark-note-{}", uuid::Uuid::now_v7().simple()));
+    let mut builder = fs::DirBuilder::new();
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder.create(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let path = dir.join("note.md");
+    let edited = write_new(&path, text).and_then(|_| run_editor(&editor, &path));
+    _ = fs::remove_dir_all(&dir);
+    edited
+}
+
+fn write_new(path: &Path, text: &str) -> Result<()> {
+    let mut file = fs::OpenOptions::new().write(true).create_new(true).open(path)?;
+    file.write_all(text.as_bytes())?;
+    Ok(())
+}
+
+fn run_editor(editor: &str, path: &Path) -> Result<String> {
+    let mut args = editor.split_whitespace();
+    let program = args.next().context("$EDITOR is empty")?;
+    let status = Command::new(program)
+        .args(args)
+        .arg(path)
+        .status()
+        .with_context(|| format!("Failed to run {}", program))?;
+    if !status.success() {
+        bail!("{} exited with {}", program, status);
+    }
+    Ok(fs::read_to_string(path)?)
+}
+
+fn print_notes(notes: &[Note]) {
+    for note in notes {
+        let edited = note.updated_at.as_ref().map(|at| format!(", edited {}", at)).unwrap_or_default();
+        println!("\n#{} ({}{})", note.seq, note.created_at, edited);
+        println!("{}", note.body.trim_end());
+    }
+}
+
+/// The time given as `in 2w` or `on 2025-01-10` by the two values of the `when` arg
+fn when(matches: &ArgMatches) -> Result<date::Datetime> {
+    let words = matches.get_many::<String>("when").unwrap().cloned().collect::<Vec<_>>();
+    date::parse_when(&words.join(" "))
+}
+
+/// Icons of the bookmarks' sites, by bookmark URL, embedded by exports when `wanted`
+fn favicons(bmark: &BMark, bookmarks: &[Bookmark], wanted: bool) -> HashMap<String, Icon> {
+    let mut icons = HashMap::new();
+    if !wanted {
+        return icons;
+    }
+    let options = FaviconOptions::default();
+    // a site which couldn't be reached once won't be reached for its next bookmark either
+    let mut unreachable = HashSet::new();
+    for b in bookmarks {
+        let host = fetch::host(&b.url);
+        if unreachable.contains(host) {
+            continue;
+        }
+        match bmark.favicon(&b.url, &options) {
+            Ok(Some(icon)) => {
+                icons.insert(b.url.clone(), icon);
+            }
+            Ok(None) => {}
+            Err(e) => {
+                eprintln!("No icon for {}: {:#}", b.url, e);
+                unreachable.insert(host);
+            }
+        }
+    }
+    icons
+}
+
+/// Ask about each scanned URL whether to add it and with which tags
+fn review(batch: Batch) -> Result<Batch> {
+    let total = batch.bookmarks.len();
+    let mut accepted = Batch { skipped: batch.skipped, ignored: batch.ignored, ..Default::default() };
+    let mut take_rest = false;
+    let mut stdin = io::stdin().lock();
+    for (i, mut new) in batch.bookmarks.into_iter().enumerate() {
+        if take_rest {
+            accepted.bookmarks.push(new);
+            continue;
+        }
+        println!("[{}/{}] {}{}", i + 1, total, new.url, new.name.as_ref().map(|n| format!(" ({})", n)).unwrap_or_default());
+        loop {
+            print!("Add it? [y]es, [s]kip, [t]ag, [a]ll remaining, [q]uit: ");
+            io::stdout().flush()?;
+            let mut answer = String::new();
+            if stdin.read_line(&mut answer)? == 0 {
+                return Ok(accepted);
+            }
+            match answer.trim() {
+                "y" | "" => accepted.bookmarks.push(new),
+                "s" => accepted.skipped.push(format!("{}: declined", new.url)),
+                "t" => {
+                    print!("Tags (comma separated): ");
+                    io::stdout().flush()?;
+                    let mut tags = String::new();
+                    stdin.read_line(&mut tags)?;
+                    new.tags.extend(tags.split(',').map(str::trim).filter(|t| !t.is_empty()).map(String::from));
+                    accepted.bookmarks.push(new);
+                }
+                "a" => {
+                    accepted.bookmarks.push(new);
+                    take_rest = true;
+                }
+                "q" => return Ok(accepted),
+                _ => continue,
+            }
+            break;
+        }
+    }
+    Ok(accepted)
+}
+
+fn print_report(report: &ImportReport) {
+    println!(
+        "Imported {} bookmark(s), skipped {} already bookmarked and {} other(s)",
+        report.added,
+        report.duplicates.len(),
+        report.skipped.len()
+    );
+    for url in &report.duplicates {
+        println!("  already bookmarked {}", url);
+    }
+    for skipped in &report.skipped {
+        println!("  skipped {}", skipped);
+    }
+    for ignored in &report.ignored {
+        println!("  ignored {}", ignored);
+    }
+}
+
+/// Show what an import would add, without adding anything
+fn print_preview(batch: &Batch, count: usize) {
+    println!("Would import {} bookmark(s), showing the first {}:", batch.bookmarks.len(), count.min(batch.bookmarks.len()));
+    for b in batch.bookmarks.iter().take(count) {
+        println!(
+            "  {}|{}|{}|{}|{}",
+            b.url,
+            b.name.as_deref().unwrap_or_default(),
+            b.tags.join(","),
+            b.category.as_deref().unwrap_or_default(),
+            b.added_at.as_deref().unwrap_or_default()
+        );
+    }
+    for skipped in &batch.skipped {
+        println!("  skipped {}", skipped);
+    }
+    for ignored in &batch.ignored {
+        println!("  ignored {}", ignored);
+    }
+}
+
+/// Seconds to wait for the page of a new bookmark, none when it shouldn't be fetched at all,
+/// see `cli::fetch_args`
+fn fetch_timeout(matches: &ArgMatches) -> Option<u64> {
+    (!matches.get_flag("no-fetch")).then(|| *matches.get_one::<u64>("timeout").unwrap())
+}
+
+/// The tag and category filter given to a command, see `cli::filter_args`
+fn filter_options(matches: &ArgMatches) -> ListOptions {
+    ListOptions {
+        tags: matches.get_many::<String>("tag").into_iter().flatten().cloned().collect(),
+        tag_mode: match matches.get_one::<String>("tag-mode").map(String::as_str) {
+            Some("all") => TagMode::All,
+            _ => TagMode::Any,
+        },
+        category: matches.get_one::<String>("category").cloned(),
+        status: matches.get_one::<ReadStatus>("status").copied(),
+        pinned: matches.get_flag("pinned"),
+        min_rating: matches.get_one::<u8>("min-rating").copied(),
+        ..Default::default()
+    }
+}
 
 fn main() -> Result<()> {
     let matches = cli::build_args();
@@ -15,7 +314,7 @@ fn main() -> Result<()> {
             if is_setup_done()? {
                 println!("Setup is already done.");
             } else {
-                let bmark = BMark::new(dbpath.unwrap().to_owned(), true)?;
+                let mut bmark = BMark::new(dbpath.unwrap(), true)?;
                 bmark.setup()?;
                 println!("Setup completed successfully!!!");
             }
@@ -32,17 +331,20 @@ fn main() -> Result<()> {
                 let tags = add_task
                     .get_many::<String>("tags")
                     .unwrap_or_default()
-                    .map(|v| v.as_str())
+                    .cloned()
                     .collect::<Vec<_>>();
                 let desc = add_task.get_one::<String>("description");
                 let category = add_task.get_one::<String>("category");
-                bmark.insert(
-                    &url,
-                    name.map(|s| s.as_str()),
+                let new = NewBookmark {
+                    url: url.to_owned(),
+                    name: name.cloned(),
                     tags,
-                    desc.map(|s| s.as_str()),
-                    category.map(|s| s.as_str()),
-                )?;
+                    desc: desc.cloned(),
+                    category: category.cloned(),
+                    ..Default::default()
+                };
+                let id = add_fetched(&mut bmark, new, fetch_timeout(add_task))?;
+                println!("{}", id);
             } else {
                 println!("You need to do setup first. Run: bmark setup --help for more info");
             }
@@ -66,11 +368,524 @@ fn main() -> Result<()> {
                 } else if column == "tags" {
                     column_type = ListColumn::Tag;
                 }
-                bmark.list(output, column_type).with_context(|| format!("Failed to list the bookmarks"))?;
+                let sort = match list_task.get_one::<String>("sort").unwrap().as_str() {
+                    "visits" => SortBy::Visits,
+                    "last-visited" => SortBy::LastVisited,
+                    _ => SortBy::Added,
+                };
+                let link = if list_task.get_flag("broken") {
+                    Some(LinkFilter::Broken)
+                } else if list_task.get_flag("redirected") {
+                    Some(LinkFilter::Redirected)
+                } else {
+                    None
+                };
+                let options = ListOptions { sort, link, ..filter_options(list_task) };
+                bmark.list(output, column_type, &options).context("Failed to list the bookmarks")?;
             } else {
                 println!("You need to do setup first nd then add the bookmarks. Run: bmark --help for more info");
             }
         }
+        Some(("open", open_task)) => {
+            if let Some(bmark) = open_bmark()? {
+                let query = open_task.get_one::<String>("query").unwrap();
+                let with = open_task.get_one::<String>("with").map(|s| s.as_str());
+                let matches = match bmark.resolve(query) {
+                    Ok(id) => vec![bmark.bookmark(&id)?],
+                    Err(_) => bmark.search(query)?,
+                };
+                let to_open = match matches.len() {
+                    0 => bail!("No bookmark matches {}", query),
+                    1 => matches,
+                    _ if open_task.get_flag("all") => matches,
+                    _ => vec![pick(matches)?],
+                };
+                for b in to_open {
+                    browser::launch(&b.url, with)?;
+                    bmark.record_visit(&b.id)?;
+                }
+            }
+        }
+        Some(("edit", edit_task)) => {
+            if let Some(mut bmark) = open_bmark()? {
+                let id = &bmark.resolve(edit_task.get_one::<String>("id").unwrap())?;
+                let edit = BookmarkEdit {
+                    url: edit_task.get_one::<String>("url").cloned(),
+                    name: edit_task.get_one::<String>("name").cloned(),
+                    desc: edit_task.get_one::<String>("description").cloned(),
+                    category: edit_task.get_one::<String>("category").cloned(),
+                    add_tags: edit_task.get_many::<String>("tags").unwrap_or_default().cloned().collect(),
+                    remove_tags: edit_task.get_many::<String>("untag").unwrap_or_default().cloned().collect(),
+                };
+                bmark.edit(id, &edit)?;
+            }
+        }
+        Some(("show", show_task)) => {
+            if let Some(bmark) = open_bmark()? {
+                let id = &bmark.resolve(show_task.get_one::<String>("id").unwrap())?;
+                if show_task.get_flag("history") {
+                    print_timeline(&bmark.revisions(id)?);
+                } else {
+                    print_bookmark(&bmark.bookmark(id)?);
+                    if let Some(meta) = bmark.page_meta(id)? {
+                        println!("title:    {}", meta.title.unwrap_or_default());
+                        println!("canonical: {}", meta.canonical_url.unwrap_or_default());
+                        println!("lang:     {}", meta.lang.unwrap_or_default());
+                    }
+                    if let Some(reminder) = bmark.reminder(id)? {
+                        println!("remind:   {}", reminder.due_at);
+                    }
+                    print_notes(&bmark.notes(id)?);
+                    for alternate in bmark.alternates(id)? {
+                        let captured = alternate.captured_at.map(|at| format!(", captured {}", at)).unwrap_or_default();
+                        println!("alternate: {} ({}{})", alternate.url, alternate.source, captured);
+                    }
+                }
+            }
+        }
+        Some(("note", note_task)) => {
+            if let Some(mut bmark) = open_bmark()? {
+                match note_task.subcommand() {
+                    Some(("add", add_task)) => {
+                        let id = &bmark.resolve(add_task.get_one::<String>("id").unwrap())?;
+                        let text = note_text(add_task)?.unwrap_or_default();
+                        let seq = bmark.add_note(id, &text)?;
+                        println!("Added note #{} to {}", seq, id);
+                    }
+                    Some(("list", list_task)) => {
+                        let id = &bmark.resolve(list_task.get_one::<String>("id").unwrap())?;
+                        print_notes(&bmark.notes(id)?);
+                    }
+                    Some(("edit", edit_task)) => {
+                        let id = &bmark.resolve(edit_task.get_one::<String>("id").unwrap())?;
+                        let seq = *edit_task.get_one::<i64>("seq").unwrap();
+                        let text = match note_text(edit_task)? {
+                            Some(text) => text,
+                            None => {
+                                let note = bmark.notes(id)?.into_iter().find(|n| n.seq == seq);
+                                let note = note.with_context(|| format!("No note #{} on bookmark {}", seq, id))?;
+                                edit_in_editor(&note.body)?
+                            }
+                        };
+                        bmark.edit_note(id, seq, &text)?;
+                        println!("Updated note #{} of {}", seq, id);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Some(("remind", remind_task)) => {
+            if let Some(mut bmark) = open_bmark()? {
+                let id = &bmark.resolve(remind_task.get_one::<String>("id").unwrap())?;
+                let due_at = when(remind_task)?;
+                bmark.remind(id, &due_at)?;
+                println!("Will remind about {} on {}", id, due_at.readable());
+            }
+        }
+        Some(("due", _)) => {
+            if let Some(bmark) = open_bmark()? {
+                for (b, reminder) in bmark.due(&date::get_current_datetime())? {
+                    println!("{}|{}|{}|due {}", b.id, b.url, b.name.unwrap_or_default(), reminder.due_at);
+                }
+            }
+        }
+        Some(("snooze", snooze_task)) => {
+            if let Some(mut bmark) = open_bmark()? {
+                let id = &bmark.resolve(snooze_task.get_one::<String>("id").unwrap())?;
+                let due_at = when(snooze_task)?;
+                bmark.snooze(id, &due_at)?;
+                println!("Snoozed {} until {}", id, due_at.readable());
+            }
+        }
+        Some(("dismiss", dismiss_task)) => {
+            if let Some(mut bmark) = open_bmark()? {
+                let id = &bmark.resolve(dismiss_task.get_one::<String>("id").unwrap())?;
+                bmark.dismiss(id)?;
+                println!("Dismissed the reminder of {}", id);
+            }
+        }
+        Some(("pin", pin_task)) => {
+            if let Some(mut bmark) = open_bmark()? {
+                let id = &bmark.resolve(pin_task.get_one::<String>("id").unwrap())?;
+                let pinned = !pin_task.get_flag("unpin");
+                bmark.pin(id, pinned)?;
+                println!("{} {}", if pinned { "Pinned" } else { "Unpinned" }, id);
+            }
+        }
+        Some(("rate", rate_task)) => {
+            if let Some(mut bmark) = open_bmark()? {
+                let id = &bmark.resolve(rate_task.get_one::<String>("id").unwrap())?;
+                let stars = rate_task.get_one::<u8>("stars").copied();
+                bmark.rate(id, stars)?;
+                match stars {
+                    Some(stars) => println!("Rated {} {} star(s)", id, stars),
+                    None => println!("Cleared the rating of {}", id),
+                }
+            }
+        }
+        Some(("later", later_task)) => {
+            if let Some(mut bmark) = open_bmark()? {
+                let url = later_task.get_one::<String>("url").unwrap();
+                if let Ok(id) = bmark.resolve(url) {
+                    bmark.set_status(&id, ReadStatus::Unread)?;
+                    println!("Queued {}, it was bookmarked already", id);
+                } else if let Ok(id) = bmark.resolve_trashed(url) {
+                    bmark.restore(&id)?;
+                    bmark.set_status(&id, ReadStatus::Unread)?;
+                    println!("Queued {}, brought back from trash", id);
+                } else {
+                    let new = NewBookmark {
+                        url: url.to_owned(),
+                        tags: later_task.get_many::<String>("tags").unwrap_or_default().cloned().collect(),
+                        status: Some(ReadStatus::Unread),
+                        ..Default::default()
+                    };
+                    println!("{}", add_fetched(&mut bmark, new, fetch_timeout(later_task))?);
+                }
+            }
+        }
+        Some(("next", next_task)) => {
+            if let Some(mut bmark) = open_bmark()? {
+                match bmark.next_unread(next_task.get_flag("random"))? {
+                    Some(b) => {
+                        if next_task.get_flag("start") {
+                            bmark.set_status(&b.id, ReadStatus::Reading)?;
+                        }
+                        print_bookmark(&bmark.bookmark(&b.id)?);
+                    }
+                    None => println!("Nothing left to read"),
+                }
+            }
+        }
+        Some(("done", done_task)) => {
+            if let Some(mut bmark) = open_bmark()? {
+                let id = &bmark.resolve(done_task.get_one::<String>("id").unwrap())?;
+                let status = if done_task.get_flag("archive") { ReadStatus::Archived } else { ReadStatus::Read };
+                bmark.set_status(id, status)?;
+                println!("Marked {} as {}", id, status.as_str());
+            }
+        }
+        Some(("search", search_task)) => {
+            if let Some(bmark) = open_bmark()? {
+                let query = search_task.get_many::<String>("query").unwrap().cloned().collect::<Vec<_>>().join(" ");
+                if search_task.get_flag("content") {
+                    for (b, snippet) in bmark.search_content(&query)? {
+                        println!("{}|{}|{}", b.id, b.url, b.name.unwrap_or_default());
+                        println!("    {}", snippet.split_whitespace().collect::<Vec<_>>().join(" "));
+                    }
+                } else {
+                    for b in bmark.search(&query)? {
+                        println!("{}|{}|{}", b.id, b.url, b.name.unwrap_or_default());
+                    }
+                }
+            }
+        }
+        Some(("revert", revert_task)) => {
+            if let Some(mut bmark) = open_bmark()? {
+                let id = &bmark.resolve(revert_task.get_one::<String>("id").unwrap())?;
+                let rev = *revert_task.get_one::<i64>("to").unwrap();
+                bmark.revert(id, rev)?;
+                println!("Reverted {} to revision {}", id, rev);
+            }
+        }
+        Some(("tag", tag_task)) => {
+            if let Some(mut bmark) = open_bmark()? {
+                if let Some(("merge", merge_task)) = tag_task.subcommand() {
+                    let from = merge_task.get_one::<String>("from").unwrap();
+                    let into = merge_task.get_one::<String>("into").unwrap();
+                    let count = bmark.merge_tags(from, into)?;
+                    println!("Merged {} into {} on {} bookmark(s)", from, into, count);
+                }
+            }
+        }
+        Some(("rm", rm_task)) => {
+            if let Some(mut bmark) = open_bmark()? {
+                let id = &bmark.resolve(rm_task.get_one::<String>("id").unwrap())?;
+                bmark.trash(id)?;
+                println!("Moved {} to trash", id);
+            }
+        }
+        Some(("trash", trash_task)) => {
+            if let Some(mut bmark) = open_bmark()? {
+                match trash_task.subcommand() {
+                    Some(("list", _)) => {
+                        for b in bmark.trashed()? {
+                            println!(
+                                "{}|{}|{}|{}",
+                                b.id,
+                                b.url,
+                                b.name.unwrap_or_default(),
+                                b.deleted_at.unwrap_or_default()
+                            );
+                        }
+                    }
+                    Some(("restore", restore_task)) => {
+                        let id = &bmark.resolve_trashed(restore_task.get_one::<String>("id").unwrap())?;
+                        bmark.restore(id)?;
+                        println!("Restored {}", id);
+                    }
+                    Some(("empty", empty_task)) => {
+                        let older_than = empty_task.get_one::<u64>("older-than").copied();
+                        let purged = bmark.empty_trash(older_than)?;
+                        println!("Purged {} bookmark(s) from trash", purged);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Some(("import", import_task)) => {
+            if let Some(mut bmark) = open_bmark()? {
+                if let Some(paths) = import_task.get_many::<PathBuf>("scan") {
+                    let mut batch = import::scan::read(&paths.cloned().collect::<Vec<_>>())?;
+                    let tags = import_task.get_many::<String>("tag").unwrap_or_default().cloned().collect::<Vec<_>>();
+                    let mut known = 0;
+                    for mut new in std::mem::take(&mut batch.bookmarks) {
+                        if bmark.is_bookmarked(&new.url)? {
+                            known += 1;
+                            continue;
+                        }
+                        new.tags.extend(tags.iter().cloned());
+                        batch.bookmarks.push(new);
+                    }
+                    if known > 0 {
+                        println!("Leaving out {} URL(s) already bookmarked", known);
+                    }
+                    if let Some(count) = import_task.get_one::<usize>("dry-run") {
+                        print_preview(&batch, *count);
+                    } else {
+                        if !import_task.get_flag("yes") {
+                            batch = review(batch)?;
+                        }
+                        print_report(&bmark.import("scan", batch)?);
+                    }
+                } else {
+                    let source = import_task.get_one::<String>("from").unwrap();
+                    let path = import_task.get_one::<PathBuf>("path").unwrap();
+                    let batch = match source.as_str() {
+                        "firefox" => import::firefox::read(path)?,
+                        "chrome" | "chromium" => import::chrome::read(path)?,
+                        "buku" => import::buku::read(path)?,
+                        "pinboard" => import::pinboard::read(path)?,
+                        "pocket" => import::pocket::read(path)?,
+                        "json" | "jsonl" => {
+                            import::json::read(path, source == "jsonl", import_task.get_flag("new-ids"))?
+                        }
+                        "csv" => {
+                            let options = CsvOptions {
+                                map: import_task.get_many::<String>("map").unwrap_or_default().cloned().collect(),
+                                tag_separator: import_task.get_one::<String>("tag-sep").unwrap().to_owned(),
+                            };
+                            let (mapping, batch) = import::csv::read(path, &options)?;
+                            for (field, _, header) in mapping {
+                                println!("{:<8} <- {}", field, header);
+                            }
+                            batch
+                        }
+                        _ => unreachable!("clap only lets known sources through"),
+                    };
+                    match import_task.get_one::<usize>("dry-run") {
+                        Some(count) => print_preview(&batch, *count),
+                        None => print_report(&bmark.import(source, batch)?),
+                    }
+                }
+            }
+        }
+        Some(("export", export_task)) => {
+            if let Some(bmark) = open_bmark()? {
+                if let Some(dir) = export_task.get_one::<PathBuf>("vault") {
+                    let report = export::vault(&bmark.bookmarks()?, dir)?;
+                    println!("Created {} notes, updated {}", report.created, report.updated);
+                } else {
+                    let out: Box<dyn Write> = match export_task.get_one::<PathBuf>("out") {
+                        Some(path) => Box::new(
+                            File::create(path).with_context(|| format!("Couldn't create {}", path.display()))?,
+                        ),
+                        None => Box::new(io::stdout().lock()),
+                    };
+                    let out = io::BufWriter::new(out);
+                    match export_task.get_one::<String>("format").unwrap().as_str() {
+                        "jsonl" => export::jsonl(&bmark.all_bookmarks()?, out)?,
+                        "markdown" => {
+                            let group_by = match export_task.get_one::<String>("group-by").unwrap().as_str() {
+                                "tag" => export::GroupBy::Tag,
+                                _ => export::GroupBy::Category,
+                            };
+                            let bookmarks = bmark.bookmarks()?;
+                            let icons = favicons(&bmark, &bookmarks, export_task.get_flag("favicons"));
+                            export::markdown(&bookmarks, group_by, &icons, out)?
+                        }
+                        _ => export::json(&bmark.all_bookmarks()?, out)?,
+                    }
+                }
+            }
+        }
+        Some(("archive", archive_task)) => {
+            if let Some(bmark) = open_bmark()? {
+                let dir = bmark.archive_dir()?;
+                match archive_task.subcommand() {
+                    Some(("open", open_task)) => {
+                        let id = &bmark.resolve(open_task.get_one::<String>("id").unwrap())?;
+                        let latest = bmark.snapshots(id)?.into_iter().next().context("The page hasn't been archived yet")?;
+                        let file = fs::canonicalize(archive::file(&dir, &latest))?;
+                        browser::launch(&format!("file://{}", file.display()), open_task.get_one::<String>("with").map(|s| s.as_str()))?;
+                    }
+                    Some(("list", list_task)) => {
+                        let id = &bmark.resolve(list_task.get_one::<String>("id").unwrap())?;
+                        for s in bmark.snapshots(id)? {
+                            println!("#{}|{}|{}|{} bytes, {} asset(s)|{}", s.id, s.archived_at, s.url, s.size, s.assets, s.hash);
+                        }
+                    }
+                    Some(("gc", _)) => {
+                        let report = bmark.archive_gc()?;
+                        println!("Deleted {} unused file(s), {} bytes", report.files, report.bytes);
+                    }
+                    _ => {
+                        let query = archive_task.get_one::<String>("query").unwrap();
+                        let matches = match bmark.resolve(query) {
+                            Ok(id) => vec![bmark.bookmark(&id)?],
+                            Err(_) => bmark.search(query)?,
+                        };
+                        if matches.is_empty() {
+                            bail!("No bookmark matches {}", query);
+                        }
+                        let options = FetchOptions {
+                            timeout: Duration::from_secs(*archive_task.get_one::<u64>("timeout").unwrap()),
+                            ..Default::default()
+                        };
+                        for b in matches {
+                            match archive::snapshot(&b.url, &dir, &options) {
+                                Ok(snapshot) => {
+                                    let s = bmark.record_snapshot(&b.id, &snapshot)?;
+                                    let html = fs::read_to_string(archive::file(&dir, &s))?;
+                                    bmark.index_text(&b.id, "page", &fetch::readable_text(&html))?;
+                                    println!("Archived {} ({} bytes, {} asset(s)) as {}", b.url, s.size, s.assets, s.hash);
+                                }
+                                Err(e) => eprintln!("Couldn't archive {}: {:#}", b.url, e),
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Some(("rescue", rescue_task)) => {
+            if let Some(mut bmark) = open_bmark()? {
+                let id = &bmark.resolve(rescue_task.get_one::<String>("id").unwrap())?;
+                let wayback = Wayback {
+                    base_url: rescue_task.get_one::<String>("archive-url").unwrap().to_owned(),
+                    options: FetchOptions {
+                        timeout: Duration::from_secs(*rescue_task.get_one::<u64>("timeout").unwrap()),
+                        ..Default::default()
+                    },
+                };
+                match bmark.rescue(id, &wayback)? {
+                    Some(copy) => println!("Found a copy captured {}: {}", copy.captured_at, copy.url),
+                    None => bail!("The archive has no copy of {}", bmark.bookmark(id)?.url),
+                }
+            }
+        }
+        Some(("check", check_task)) => {
+            if let Some(mut bmark) = open_bmark()? {
+                let options = CheckOptions {
+                    parallel: *check_task.get_one::<usize>("parallel").unwrap(),
+                    per_host_delay: Duration::from_millis(*check_task.get_one::<u64>("per-host-delay").unwrap()),
+                    fetch: FetchOptions {
+                        timeout: Duration::from_secs(*check_task.get_one::<u64>("timeout").unwrap()),
+                        ..Default::default()
+                    },
+                };
+                let links = bmark.query(&filter_options(check_task))?.into_iter().map(|b| (b.id, b.url)).collect::<Vec<_>>();
+                let total = links.len();
+                // each result is kept as soon as it's in, so an interrupted run isn't lost
+                let statuses = check::check(links, &options, |s| {
+                    if s.is_broken() {
+                        let why = s.error.clone().or(s.status.map(|s| s.to_string())).unwrap_or_default();
+                        println!("broken     {} ({})", s.url, why);
+                    } else if s.is_redirected() {
+                        let kind = if s.permanent { "moved" } else { "redirected" };
+                        println!("{:<10} {} -> {}", kind, s.url, s.final_url);
+                    }
+                    bmark.record_link_status(s)
+                })?;
+                let broken = statuses.iter().filter(|s| s.is_broken()).count();
+                let redirected = statuses.iter().filter(|s| !s.is_broken() && s.is_redirected()).count();
+                println!("Checked {} link(s): {} broken, {} redirected", total, broken, redirected);
+
+                if check_task.get_flag("fix-redirects") {
+                    let fixed = bmark.fix_redirects()?;
+                    for (id, old, new) in &fixed {
+                        println!("Moved {} from {} to {}", id, old, new);
+                    }
+                    println!("Fixed {} permanent redirect(s)", fixed.len());
+                }
+            }
+        }
+        Some(("publish", publish_task)) => {
+            if let Some(bmark) = open_bmark()? {
+                let dir = publish_task.get_one::<PathBuf>("out").unwrap();
+                let title = publish_task.get_one::<String>("title").unwrap();
+                let bookmarks = bmark.query(&filter_options(publish_task))?;
+                let icons = favicons(&bmark, &bookmarks, publish_task.get_flag("favicons"));
+                let report = publish::site(&bookmarks, dir, title, &icons)?;
+                println!(
+                    "Published {} bookmarks with {} tag and {} category pages to {}",
+                    report.bookmarks,
+                    report.tags,
+                    report.categories,
+                    dir.display()
+                );
+            }
+        }
+        Some(("feed", feed_task)) => {
+            if let Some(bmark) = open_bmark()? {
+                let options = filter_options(feed_task);
+                let mut bookmarks = bmark.query(&options)?;
+                let query = feed_task.get_one::<String>("query");
+                if let Some(query) = query {
+                    let matching = bmark.search(query)?.into_iter().map(|b| b.id).collect::<HashSet<_>>();
+                    bookmarks.retain(|b| matching.contains(&b.id));
+                }
+
+                let selection = feed::selection(&options, query.map(String::as_str));
+                let feed = feed::Feed {
+                    title: feed_task.get_one::<String>("title").unwrap_or(&selection),
+                    id: feed::feed_id(&selection),
+                    link: feed_task.get_one::<String>("link").map(String::as_str),
+                };
+                let format = match feed_task.get_one::<String>("format").unwrap().as_str() {
+                    "rss" => feed::FeedFormat::Rss,
+                    _ => feed::FeedFormat::Atom,
+                };
+                feed::write(format, &feed, &bookmarks, io::BufWriter::new(io::stdout().lock()))?;
+            }
+        }
+        Some(("undo", undo_task)) => {
+            if let Some(mut bmark) = open_bmark()? {
+                let count = *undo_task.get_one::<usize>("count").unwrap();
+                let undone = bmark.undo(count)?;
+                if undone.is_empty() {
+                    println!("Nothing to undo");
+                }
+                for op in undone {
+                    println!("Undid #{} {} {}", op.id, op.kind, op.summary);
+                }
+            }
+        }
+        Some(("history", history_task)) => {
+            if let Some(bmark) = open_bmark()? {
+                let limit = *history_task.get_one::<usize>("limit").unwrap();
+                for op in bmark.history(limit)? {
+                    println!(
+                        "#{}|{}|{}|{}|{} row(s){}",
+                        op.id,
+                        op.created_at,
+                        op.kind,
+                        op.summary,
+                        op.rows,
+                        op.undone_at.map(|at| format!("|undone at {}", at)).unwrap_or_default()
+                    );
+                }
+            }
+        }
         _ => {}
     }
 
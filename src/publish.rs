@@ -0,0 +1,305 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+use serde_json::json;
+
+use crate::{
+    favicon::Icon,
+    feed::{self, Feed},
+    Bookmark,
+};
+
+const STYLE: &str = "body { font-family: sans-serif; max-width: 60em; margin: 2em auto; padding: 0 1em; line-height: 1.5; }
+nav a { margin-right: 0.75em; }
+input[type=search] { width: 100%; padding: 0.4em; font-size: 1em; margin: 1em 0; }
+ul.bookmarks { list-style: none; padding: 0; }
+ul.bookmarks li { margin: 0.8em 0; }
+img.favicon { width: 16px; height: 16px; margin-right: 0.4em; vertical-align: middle; }
+.desc { color: #444; }
+.meta, .meta a { color: #777; font-size: 0.9em; }
+";
+
+/// Filters the page's bookmarks against the index as one types, without any request
+const SEARCH: &str = "(function () {
+  var input = document.getElementById('search');
+  var results = document.getElementById('results');
+  var listing = document.getElementById('listing');
+  if (!input || !window.BMARK_INDEX) return;
+  input.addEventListener('input', function () {
+    var words = input.value.toLowerCase().split(/\\s+/).filter(Boolean);
+    results.textContent = '';
+    listing.hidden = words.length > 0;
+    if (!words.length) return;
+    window.BMARK_INDEX.forEach(function (b) {
+      var text = [b.name, b.url, b.desc, b.category].concat(b.tags).join(' ').toLowerCase();
+      if (!words.every(function (w) { return text.indexOf(w) !== -1; })) return;
+      var li = document.createElement('li');
+      var a = document.createElement(b.href ? 'a' : 'span');
+      if (b.href) a.href = b.href;
+      a.textContent = b.name || b.url;
+      li.appendChild(a);
+      if (b.desc) {
+        var desc = document.createElement('div');
+        desc.className = 'desc';
+        desc.textContent = b.desc;
+        li.appendChild(desc);
+      }
+      results.appendChild(li);
+    });
+  });
+})();
+";
+
+/// Pages `site` wrote
+#[derive(Debug, Default)]
+pub struct SiteReport {
+    pub bookmarks: usize,
+    pub tags: usize,
+    pub categories: usize,
+}
+
+pub(crate) fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Whether the page may link to `url`, `javascript:` and `data:` URLs would run in the page
+/// when clicked so those are shown as text
+fn linkable(url: &str) -> bool {
+    url.split_once(':')
+        .is_some_and(|(scheme, _)| scheme.eq_ignore_ascii_case("http") || scheme.eq_ignore_ascii_case("https"))
+}
+
+/// File name safe form of a tag or category, `dev/Rust lang` becomes `dev-rust-lang`
+pub(crate) fn slug(name: &str) -> String {
+    let slug = name
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+    if slug.is_empty() {
+        String::from("-")
+    } else {
+        slug
+    }
+}
+
+/// Give each name its own slug, numbering the ones which end up alike
+fn slugs<'a>(names: impl Iterator<Item = &'a String>) -> BTreeMap<&'a String, String> {
+    let mut slugs: BTreeMap<&String, String> = BTreeMap::new();
+    for name in names {
+        let base = slug(name);
+        let mut candidate = base.clone();
+        let mut n = 2;
+        while slugs.values().any(|s| *s == candidate) {
+            candidate = format!("{}-{}", base, n);
+            n += 1;
+        }
+        slugs.insert(name, candidate);
+    }
+    slugs
+}
+
+struct Site<'a> {
+    title: &'a str,
+    tags: BTreeMap<&'a String, String>,
+    categories: BTreeMap<&'a String, String>,
+    icons: &'a HashMap<String, Icon>,
+}
+
+impl Site<'_> {
+    /// `root` leads from the page back to the top of the site, like `../`, `feed` is the
+    /// page's own Atom feed
+    fn page(&self, root: &str, heading: &str, feed: Option<&str>, bookmarks: &[&Bookmark]) -> String {
+        let mut html = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+             <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+             <title>{title}</title>\n<link rel=\"stylesheet\" href=\"{root}style.css\">\n{feed}</head>\n<body>\n\
+             <h1><a href=\"{root}index.html\">{site}</a></h1>\n",
+            title = escape_html(&format!("{} · {}", heading, self.title)),
+            site = escape_html(self.title),
+            root = root,
+            feed = feed
+                .map(|f| format!("<link rel=\"alternate\" type=\"application/atom+xml\" href=\"{}\">\n", f))
+                .unwrap_or_default(),
+        );
+        if !self.categories.is_empty() {
+            html.push_str("<nav>Categories: ");
+            for (category, slug) in &self.categories {
+                html.push_str(&format!("<a href=\"{}categories/{}.html\">{}</a>", root, slug, escape_html(category)));
+            }
+            html.push_str("</nav>\n");
+        }
+        if !self.tags.is_empty() {
+            html.push_str("<nav>Tags: ");
+            for (tag, slug) in &self.tags {
+                html.push_str(&format!("<a href=\"{}tags/{}.html\">#{}</a>", root, slug, escape_html(tag)));
+            }
+            html.push_str("</nav>\n");
+        }
+        html.push_str("<input type=\"search\" id=\"search\" placeholder=\"Search all bookmarks\" autocomplete=\"off\">\n");
+        html.push_str("<ul class=\"bookmarks\" id=\"results\"></ul>\n");
+        html.push_str(&format!("<section id=\"listing\">\n<h2>{}</h2>\n<ul class=\"bookmarks\">\n", escape_html(heading)));
+        for b in bookmarks {
+            html.push_str(&self.entry(root, b));
+        }
+        html.push_str(&format!(
+            "</ul>\n</section>\n<script src=\"{root}search-index.js\"></script>\n<script src=\"{root}search.js\"></script>\n\
+             </body>\n</html>\n",
+            root = root
+        ));
+        html
+    }
+
+    fn entry(&self, root: &str, b: &Bookmark) -> String {
+        let name = b.name.as_deref().filter(|n| !n.is_empty()).unwrap_or(&b.url);
+        let icon = self
+            .icons
+            .get(&b.url)
+            .map(|icon| format!("<img class=\"favicon\" src=\"{}\" alt=\"\">", escape_html(&icon.data_uri())))
+            .unwrap_or_default();
+        let mut entry = if linkable(&b.url) {
+            format!("<li>{}<a href=\"{}\">{}</a>", icon, escape_html(&b.url), escape_html(name))
+        } else {
+            format!("<li>{}<span title=\"{}\">{}</span>", icon, escape_html(&b.url), escape_html(name))
+        };
+        if let Some(desc) = b.desc.as_deref().filter(|d| !d.is_empty()) {
+            entry.push_str(&format!("<div class=\"desc\">{}</div>", escape_html(desc)));
+        }
+        let mut meta = vec![];
+        if let Some(category) = &b.category {
+            meta.push(format!("<a href=\"{}categories/{}.html\">{}</a>", root, self.categories[category], escape_html(category)));
+        }
+        for tag in &b.tags {
+            meta.push(format!("<a href=\"{}tags/{}.html\">#{}</a>", root, self.tags[tag], escape_html(tag)));
+        }
+        meta.push(escape_html(&b.added_at));
+        entry.push_str(&format!("<div class=\"meta\">{}</div></li>\n", meta.join(" · ")));
+        entry
+    }
+}
+
+/// Write `index.html`, a page per tag under `tags/` and one per category under `categories/`,
+/// along with the search index and Atom feeds of the site and of each tag, into `dir`.
+/// Everything links relatively and nothing is fetched, so the site can be served from
+/// anywhere or opened straight from disk. Icons found in `icons` by the bookmark's URL are
+/// embedded, for the same reason.
+pub fn site(bookmarks: &[Bookmark], dir: &Path, title: &str, icons: &HashMap<String, Icon>) -> Result<SiteReport> {
+    let mut by_tag: BTreeMap<&String, Vec<&Bookmark>> = BTreeMap::new();
+    let mut by_category: BTreeMap<&String, Vec<&Bookmark>> = BTreeMap::new();
+    for b in bookmarks {
+        for tag in &b.tags {
+            by_tag.entry(tag).or_default().push(b);
+        }
+        if let Some(category) = &b.category {
+            by_category.entry(category).or_default().push(b);
+        }
+    }
+    let site = Site {
+        title,
+        tags: slugs(by_tag.keys().copied()),
+        categories: slugs(by_category.keys().copied()),
+        icons,
+    };
+
+    for sub in ["tags", "categories"] {
+        let sub = dir.join(sub);
+        // pages of tags and categories which no longer exist shouldn't linger
+        if sub.is_dir() {
+            for entry in fs::read_dir(&sub)? {
+                let path = entry?.path();
+                if path.extension().is_some_and(|e| e == "html" || e == "xml") {
+                    fs::remove_file(path)?;
+                }
+            }
+        }
+        fs::create_dir_all(&sub).with_context(|| format!("Couldn't create {}", sub.display()))?;
+    }
+
+    let all = bookmarks.iter().collect::<Vec<_>>();
+    fs::write(dir.join("index.html"), site.page("", "All bookmarks", Some("feed.xml"), &all))?;
+    let feed = Feed { title, id: feed::feed_id(title), link: None };
+    feed::atom(&feed, bookmarks, fs::File::create(dir.join("feed.xml"))?)?;
+    for (tag, bookmarks) in &by_tag {
+        let slug = &site.tags[tag];
+        let page = site.page("../", &format!("#{}", tag), Some(&format!("{}.xml", slug)), bookmarks);
+        fs::write(dir.join("tags").join(format!("{}.html", slug)), page)?;
+        // so teammates can follow a tag
+        let title = format!("#{} · {}", tag, title);
+        let feed = Feed { title: &title, id: feed::feed_id(&format!("{} tag {}", site.title, tag)), link: None };
+        let bookmarks = bookmarks.iter().map(|b| (*b).clone()).collect::<Vec<_>>();
+        feed::atom(&feed, &bookmarks, fs::File::create(dir.join("tags").join(format!("{}.xml", slug)))?)?;
+    }
+    for (category, bookmarks) in &by_category {
+        let page = site.page("../", category, None, bookmarks);
+        fs::write(dir.join("categories").join(format!("{}.html", site.categories[category])), page)?;
+    }
+
+    // a script rather than json, browsers won't fetch json from a page opened as a file
+    let index = bookmarks
+        .iter()
+        .map(|b| {
+            let href = linkable(&b.url).then_some(&b.url);
+            json!({ "name": b.name, "url": b.url, "href": href, "desc": b.desc, "category": b.category, "tags": b.tags })
+        })
+        .collect::<Vec<_>>();
+    fs::write(dir.join("search-index.js"), format!("window.BMARK_INDEX = {};\n", serde_json::to_string(&index)?))?;
+    fs::write(dir.join("search.js"), SEARCH)?;
+    fs::write(dir.join("style.css"), STYLE)?;
+
+    Ok(SiteReport { bookmarks: bookmarks.len(), tags: by_tag.len(), categories: by_category.len() })
+}
+
+#[test]
+fn writes_pages_and_index() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("bmark-publish-test-{}", std::process::id()));
+    _ = fs::remove_dir_all(&dir);
+    let bookmarks = vec![
+        Bookmark {
+            url: "https://docs.rs/?a=1&b=2".into(),
+            name: Some("<Docs>".into()),
+            tags: vec!["rust".into(), "Rust!".into()],
+            category: Some("dev/rust".into()),
+            ..Default::default()
+        },
+        Bookmark { url: "https://example.com/".into(), ..Default::default() },
+        Bookmark { url: "javascript:alert(1)".into(), name: Some("Click".into()), ..Default::default() },
+    ];
+    fs::create_dir_all(dir.join("tags"))?;
+    fs::write(dir.join("tags").join("stale.html"), "")?;
+
+    let icons = HashMap::from([(
+        String::from("https://example.com/"),
+        Icon { bytes: b"png".to_vec(), mime: String::from("image/png") },
+    )]);
+    let report = site(&bookmarks, &dir, "Team links", &icons)?;
+    assert_eq!((report.bookmarks, report.tags, report.categories), (3, 2, 1));
+    let index = fs::read_to_string(dir.join("index.html"))?;
+    assert!(index.contains("<a href=\"https://docs.rs/?a=1&amp;b=2\">&lt;Docs&gt;</a>"));
+    assert!(index.contains("<a href=\"categories/dev-rust.html\">dev/rust</a>"));
+    assert!(index.contains("<li><img class=\"favicon\" src=\"data:image/png;base64,cG5n\" alt=\"\"><a href=\"https://example.com/\">"));
+    assert!(index.contains("<li><span title=\"javascript:alert(1)\">Click</span>"));
+    assert!(!index.contains("href=\"javascript:"));
+    // both tags slug to `rust`, the second one is numbered
+    assert!(index.contains("<a href=\"tags/rust-2.html\">#rust</a>"));
+    let tag_page = fs::read_to_string(dir.join("tags").join("rust.html"))?;
+    assert!(tag_page.contains("<script src=\"../search-index.js\">"));
+    assert!(tag_page.contains("href=\"rust.xml\""));
+    assert!(dir.join("tags").join("rust.xml").exists());
+    assert!(!dir.join("tags").join("stale.html").exists());
+    let search_index = fs::read_to_string(dir.join("search-index.js"))?;
+    assert!(search_index.starts_with("window.BMARK_INDEX = [{"));
+    assert!(search_index.contains("\"href\":\"https://example.com/\""));
+    assert!(search_index.contains("\"href\":null,\"name\":\"Click\""));
+
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
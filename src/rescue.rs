@@ -0,0 +1,109 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    date::{self, Datetime},
+    fetch::{self, FetchOptions},
+};
+
+/// Where the availability API of the Wayback Machine lives
+pub const WAYBACK_URL: &str = "https://archive.org/wayback/available";
+
+/// A copy of a page kept by a web archive
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchivedCopy {
+    pub url: String,
+    /// When the archive captured the page, `yyyy-mm-dd hh:mm:ss`
+    pub captured_at: String,
+}
+
+/// Another place to read a bookmark's page at, like an archived copy of it
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Alternate {
+    pub url: String,
+    /// Where the link came from, the name of the archive for archived copies
+    pub source: String,
+    pub captured_at: Option<String>,
+    pub added_at: String,
+}
+
+/// A web archive which can be asked for its copies of a page
+pub trait ArchiveProvider {
+    /// Short name the copies are recorded under
+    fn name(&self) -> &str;
+
+    /// The archive's copy of `url` captured closest to `at`, if it has any
+    fn closest(&self, url: &str, at: &Datetime) -> Result<Option<ArchivedCopy>>;
+}
+
+/// The Wayback Machine of the Internet Archive, or anything answering like its availability API
+pub struct Wayback {
+    pub base_url: String,
+    pub options: FetchOptions,
+}
+
+impl Default for Wayback {
+    fn default() -> Self {
+        Wayback { base_url: String::from(WAYBACK_URL), options: FetchOptions::default() }
+    }
+}
+
+impl ArchiveProvider for Wayback {
+    fn name(&self) -> &str {
+        "wayback"
+    }
+
+    fn closest(&self, url: &str, at: &Datetime) -> Result<Option<ArchivedCopy>> {
+        let body = fetch::agent_builder(&self.options)
+            .build()
+            .get(&self.base_url)
+            .query("url", url)
+            .query("timestamp", &at.digits())
+            .call()
+            .context("Couldn't reach the archive")?
+            .into_string()?;
+        let answer: Value = serde_json::from_str(&body).context("The archive didn't answer with json")?;
+        // `{"archived_snapshots": {}}` when there is no copy at all
+        let closest = &answer["archived_snapshots"]["closest"];
+        if closest["available"] != Value::Bool(true) {
+            return Ok(None);
+        }
+        let copy_url = closest["url"].as_str().context("The archive's answer has no url")?;
+        let timestamp = closest["timestamp"].as_str().context("The archive's answer has no timestamp")?;
+        Ok(Some(ArchivedCopy {
+            // the API answers with http links though the archive is served over https
+            url: copy_url.replacen("http://web.archive.org/", "https://web.archive.org/", 1),
+            captured_at: date::parse_digits(timestamp)?.to_string(),
+        }))
+    }
+}
+
+#[test]
+fn asks_wayback_for_the_closest_copy() -> Result<()> {
+    use crate::fetch::{response, serve};
+
+    let base = serve(|path| {
+        let body = if path.contains("url=https%3A%2F%2Fexample.com%2Fgone&timestamp=20240805140309") {
+            r#"{"url": "https://example.com/gone", "archived_snapshots": {"closest": {"status": "200", "available": true,
+                "url": "http://web.archive.org/web/20240801000000/https://example.com/gone", "timestamp": "20240801000000"}}}"#
+        } else {
+            r#"{"url": "https://example.com/never", "archived_snapshots": {}}"#
+        };
+        response("200 OK", &[("Content-Type", "application/json")], body)
+    });
+    let wayback = Wayback { base_url: format!("{}/wayback/available", base), ..Default::default() };
+    let at = date::parse_date("2024-08-05 14:03:09")?;
+
+    let copy = wayback.closest("https://example.com/gone", &at)?;
+    assert_eq!(
+        copy,
+        Some(ArchivedCopy {
+            url: String::from("https://web.archive.org/web/20240801000000/https://example.com/gone"),
+            captured_at: String::from("2024-08-01 00:00:00"),
+        })
+    );
+    assert_eq!(wayback.closest("https://example.com/never", &at)?, None);
+    Ok(())
+}